@@ -0,0 +1,14 @@
+#!/bin/bash
+# Wrapper for /cyril/post.html - Pre-rendered post pages for link previews
+
+case "$REQUEST_METHOD" in
+    GET)
+        exec /usr/lib/cgi-bin/blog-page
+        ;;
+    *)
+        echo "Status: 405 Method Not Allowed"
+        echo "Content-Type: text/plain"
+        echo ""
+        echo 'Method not allowed'
+        ;;
+esac
@@ -0,0 +1,20 @@
+#!/bin/bash
+# Wrapper for /cyril/api/post/preview - Draft preview links
+
+case "$REQUEST_METHOD" in
+    GET)
+        exec /usr/lib/cgi-bin/blog-preview
+        ;;
+    POST)
+        exec /usr/lib/cgi-bin/blog-preview
+        ;;
+    DELETE)
+        exec /usr/lib/cgi-bin/blog-preview
+        ;;
+    *)
+        echo "Status: 405 Method Not Allowed"
+        echo "Content-Type: application/json"
+        echo ""
+        echo '{"error":"Method not allowed"}'
+        ;;
+esac
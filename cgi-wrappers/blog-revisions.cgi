@@ -0,0 +1,17 @@
+#!/bin/bash
+# Wrapper for /cyril/api/post/revisions - Post revision history
+
+case "$REQUEST_METHOD" in
+    GET)
+        exec /usr/lib/cgi-bin/blog-revisions
+        ;;
+    POST)
+        exec /usr/lib/cgi-bin/blog-revisions
+        ;;
+    *)
+        echo "Status: 405 Method Not Allowed"
+        echo "Content-Type: application/json"
+        echo ""
+        echo '{"error":"Method not allowed"}'
+        ;;
+esac
@@ -0,0 +1,14 @@
+#!/bin/bash
+# Wrapper for /cyril/sitemap.xml and /cyril/robots.txt
+
+case "$REQUEST_METHOD" in
+    GET)
+        exec /usr/lib/cgi-bin/blog-sitemap
+        ;;
+    *)
+        echo "Status: 405 Method Not Allowed"
+        echo "Content-Type: application/xml"
+        echo ""
+        echo '<?xml version="1.0" encoding="UTF-8"?><error>Method not allowed</error>'
+        ;;
+esac
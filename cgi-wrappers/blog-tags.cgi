@@ -0,0 +1,14 @@
+#!/bin/bash
+# Wrapper for /cyril/api/tags - Tag cloud
+
+case "$REQUEST_METHOD" in
+    GET)
+        exec /usr/lib/cgi-bin/blog-tags
+        ;;
+    *)
+        echo "Status: 405 Method Not Allowed"
+        echo "Content-Type: application/json"
+        echo ""
+        echo '{"error":"Method not allowed"}'
+        ;;
+esac
@@ -0,0 +1,17 @@
+#!/bin/bash
+# Wrapper for /cyril/api/trash - Trash listing and restore
+
+case "$REQUEST_METHOD" in
+    GET)
+        exec /usr/lib/cgi-bin/blog-trash
+        ;;
+    POST)
+        exec /usr/lib/cgi-bin/blog-trash
+        ;;
+    *)
+        echo "Status: 405 Method Not Allowed"
+        echo "Content-Type: application/json"
+        echo ""
+        echo '{"error":"Method not allowed"}'
+        ;;
+esac
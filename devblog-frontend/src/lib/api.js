@@ -1,13 +1,28 @@
 const API_BASE = 'https://dw.ramsden-international.com/devblog/api';
 
-export async function getPosts() {
-  const res = await fetch(`${API_BASE}/posts`);
+// filters: { tag, project, repo, after, before } - all optional
+export async function getPosts(filters = {}) {
+  const params = new URLSearchParams();
+  for (const [key, value] of Object.entries(filters)) {
+    if (value) params.set(key, value);
+  }
+  const query = params.toString();
+  const res = await fetch(`${API_BASE}/posts${query ? `?${query}` : ''}`);
   if (!res.ok) throw new Error('Failed to fetch posts');
-  return res.json();
+  const page = await res.json();
+  return page.posts;
 }
 
-export async function getPost(slug) {
-  const res = await fetch(`${API_BASE}/post?slug=${encodeURIComponent(slug)}`);
+// preview is a draft preview token from a shared link, if any
+export async function getPost(slug, preview = null) {
+  const params = new URLSearchParams({ slug });
+  if (preview) params.set('preview', preview);
+  const res = await fetch(`${API_BASE}/post?${params}`);
+  if (res.status === 301) {
+    // Renamed post - the old slug still resolves to the new one
+    const moved = await res.json();
+    return getPost(moved.slug, preview);
+  }
   if (!res.ok) {
     if (res.status === 404) return null;
     throw new Error('Failed to fetch post');
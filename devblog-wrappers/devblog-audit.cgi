@@ -0,0 +1,17 @@
+#!/bin/bash
+# Wrapper for /devblog/api/audit - Audit log
+
+export BLOG_DB_PATH="/var/lib/devblog/blog.db"
+export BLOG_API_KEY_PATH="/etc/devblog-api-key"
+
+case "$REQUEST_METHOD" in
+    GET)
+        exec /usr/lib/cgi-bin/blog-audit
+        ;;
+    *)
+        echo "Status: 405 Method Not Allowed"
+        echo "Content-Type: application/json"
+        echo ""
+        echo '{"error":"Method not allowed"}'
+        ;;
+esac
@@ -0,0 +1,18 @@
+#!/bin/bash
+# Wrapper for /devblog/post.html - Pre-rendered post pages for link previews
+
+export BLOG_DB_PATH="/var/lib/devblog/blog.db"
+export BLOG_API_KEY_PATH="/etc/devblog-api-key"
+export BLOG_BASE_URL="https://steponnopets.net/devblog"
+
+case "$REQUEST_METHOD" in
+    GET)
+        exec /usr/lib/cgi-bin/blog-page
+        ;;
+    *)
+        echo "Status: 405 Method Not Allowed"
+        echo "Content-Type: text/plain"
+        echo ""
+        echo 'Method not allowed'
+        ;;
+esac
@@ -0,0 +1,24 @@
+#!/bin/bash
+# Wrapper for /devblog/api/post/preview - Draft preview links
+
+export BLOG_DB_PATH="/var/lib/devblog/blog.db"
+export BLOG_API_KEY_PATH="/etc/devblog-api-key"
+export BLOG_BASE_URL="https://steponnopets.net/devblog"
+
+case "$REQUEST_METHOD" in
+    GET)
+        exec /usr/lib/cgi-bin/blog-preview
+        ;;
+    POST)
+        exec /usr/lib/cgi-bin/blog-preview
+        ;;
+    DELETE)
+        exec /usr/lib/cgi-bin/blog-preview
+        ;;
+    *)
+        echo "Status: 405 Method Not Allowed"
+        echo "Content-Type: application/json"
+        echo ""
+        echo '{"error":"Method not allowed"}'
+        ;;
+esac
@@ -0,0 +1,20 @@
+#!/bin/bash
+# Wrapper for /devblog/api/post/revisions - Post revision history
+
+export BLOG_DB_PATH="/var/lib/devblog/blog.db"
+export BLOG_API_KEY_PATH="/etc/devblog-api-key"
+
+case "$REQUEST_METHOD" in
+    GET)
+        exec /usr/lib/cgi-bin/blog-revisions
+        ;;
+    POST)
+        exec /usr/lib/cgi-bin/blog-revisions
+        ;;
+    *)
+        echo "Status: 405 Method Not Allowed"
+        echo "Content-Type: application/json"
+        echo ""
+        echo '{"error":"Method not allowed"}'
+        ;;
+esac
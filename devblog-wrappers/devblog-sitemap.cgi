@@ -0,0 +1,18 @@
+#!/bin/bash
+# Wrapper for /devblog/sitemap.xml and /devblog/robots.txt
+
+export BLOG_DB_PATH="/var/lib/devblog/blog.db"
+export BLOG_API_KEY_PATH="/etc/devblog-api-key"
+export BLOG_BASE_URL="https://steponnopets.net/devblog"
+
+case "$REQUEST_METHOD" in
+    GET)
+        exec /usr/lib/cgi-bin/blog-sitemap
+        ;;
+    *)
+        echo "Status: 405 Method Not Allowed"
+        echo "Content-Type: application/xml"
+        echo ""
+        echo '<?xml version="1.0" encoding="UTF-8"?><error>Method not allowed</error>'
+        ;;
+esac
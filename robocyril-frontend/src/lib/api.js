@@ -3,11 +3,20 @@ const API_BASE = 'https://steponnopets.net/cyril/api';
 export async function getPosts() {
   const res = await fetch(`${API_BASE}/posts`);
   if (!res.ok) throw new Error('Failed to fetch posts');
-  return res.json();
+  const page = await res.json();
+  return page.posts;
 }
 
-export async function getPost(slug) {
-  const res = await fetch(`${API_BASE}/post?slug=${encodeURIComponent(slug)}`);
+// preview is a draft preview token from a shared link, if any
+export async function getPost(slug, preview = null) {
+  const params = new URLSearchParams({ slug });
+  if (preview) params.set('preview', preview);
+  const res = await fetch(`${API_BASE}/post?${params}`);
+  if (res.status === 301) {
+    // Renamed post - the old slug still resolves to the new one
+    const moved = await res.json();
+    return getPost(moved.slug, preview);
+  }
   if (!res.ok) {
     if (res.status === 404) return null;
     throw new Error('Failed to fetch post');
@@ -0,0 +1,158 @@
+use chrono::{Duration, Utc};
+use robocyril_api::auth::{issue_api_key, list_api_keys, revoke_api_key, Scope};
+use robocyril_api::{open_db, purge_trash};
+use rusqlite::Connection;
+
+const USAGE: &str = "Usage: blog-admin <command>
+
+Commands:
+  purge-trash <days>                   Permanently delete posts trashed more than <days> days ago
+  issue-key <label> <scopes> [days]    Create an API key, optionally expiring after <days> days
+  list-keys                            Show every API key (never the keys themselves)
+  revoke-key <id>                      Stop an API key working
+
+Scopes are comma-separated: create, update, publish, delete, read-drafts,
+read-audit, or all for every one.";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args.first().map(|s| s.as_str()) {
+        Some("purge-trash") => purge(&args[1..]),
+        Some("issue-key") => issue_key(&args[1..]),
+        Some("list-keys") if args.len() == 1 => list_keys(),
+        Some("revoke-key") => revoke_key(&args[1..]),
+        _ => usage(),
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
+
+fn open() -> Connection {
+    match open_db() {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("Failed to open database: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn fail(what: &str, e: rusqlite::Error) -> ! {
+    eprintln!("{} failed: {}", what, e);
+    std::process::exit(1);
+}
+
+fn purge(args: &[String]) {
+    let days: i64 = match args.first().and_then(|d| d.parse().ok()) {
+        Some(d) if d >= 0 => d,
+        _ => usage(),
+    };
+
+    match purge_trash(&open(), days) {
+        Ok(n) => println!("Purged {} post(s) trashed more than {} day(s) ago", n, days),
+        Err(e) => fail("Purge", e),
+    }
+}
+
+fn parse_scopes(list: &str) -> Option<Vec<Scope>> {
+    if list == "all" {
+        return Some(Scope::ALL.to_vec());
+    }
+
+    let mut scopes = Vec::new();
+    for name in list.split(',').map(str::trim) {
+        let scope = Scope::parse(name)?;
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    Some(scopes)
+}
+
+fn issue_key(args: &[String]) {
+    let (label, scopes, days) = match args {
+        [label, scopes] => (label, scopes, None),
+        [label, scopes, days] => match days.parse::<i64>() {
+            Ok(d) if d > 0 => (label, scopes, Some(d)),
+            _ => usage(),
+        },
+        _ => usage(),
+    };
+
+    let scopes = match parse_scopes(scopes) {
+        Some(s) if !s.is_empty() => s,
+        _ => {
+            eprintln!("Unknown scope in '{}'", scopes);
+            usage();
+        }
+    };
+
+    let expires_at = days.map(|d| Utc::now() + Duration::days(d));
+
+    match issue_api_key(&open(), label, &scopes, expires_at) {
+        Ok(issued) => {
+            // This is the only time either can be shown
+            eprintln!(
+                "Issued key {} ({}). Save both lines now; they can't be shown again.\n\
+                 The first is the key, the second the secret for signing requests:",
+                issued.id, label
+            );
+            println!("{}\n{}", issued.key, issued.signing_secret);
+        }
+        Err(e) => fail("Issuing key", e),
+    }
+}
+
+fn list_keys() {
+    let keys = match list_api_keys(&open()) {
+        Ok(k) => k,
+        Err(e) => fail("Listing keys", e),
+    };
+
+    let date = |dt: Option<chrono::DateTime<Utc>>| dt.map(|d| d.format("%Y-%m-%d %H:%M").to_string());
+
+    for key in keys {
+        let state = if key.revoked_at.is_some() {
+            "revoked"
+        } else if !key.is_active() {
+            "expired"
+        } else {
+            "active"
+        };
+        let scopes: Vec<&str> = key.scopes.iter().map(Scope::as_str).collect();
+
+        println!(
+            "{:>4}  {:<20} {:<8} {}\n      created {}, expires {}, last used {}",
+            key.id,
+            key.label,
+            state,
+            scopes.join(","),
+            date(Some(key.created_at)).unwrap_or_default(),
+            date(key.expires_at).unwrap_or_else(|| "never".to_string()),
+            date(key.last_used_at).unwrap_or_else(|| "never".to_string()),
+        );
+    }
+}
+
+fn revoke_key(args: &[String]) {
+    let id: i64 = match args {
+        [id] => match id.parse() {
+            Ok(id) => id,
+            Err(_) => usage(),
+        },
+        _ => usage(),
+    };
+
+    match revoke_api_key(&open(), id) {
+        Ok(true) => println!("Revoked key {}", id),
+        Ok(false) => {
+            eprintln!("No active key with id {}", id);
+            std::process::exit(1);
+        }
+        Err(e) => fail("Revoking key", e),
+    }
+}
@@ -0,0 +1,121 @@
+use crate::parse_time;
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, Result};
+use serde::Serialize;
+use serde_json::Value;
+
+// A row in audit_log for every change made through the API, so when a post
+// changes or vanishes there's a record of which key did it and from where.
+
+#[derive(Debug, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    /// Label of the key that made the change
+    pub key_label: String,
+    pub remote_addr: String,
+    /// create, update, delete, restore or restore-revision
+    pub action: String,
+    pub slug: String,
+    /// The fields the request set, as summarised by `summarise_changes`
+    pub changes: Value,
+}
+
+/// A JSON summary of a create or update body: just the fields that were
+/// given, with content cut down to its length so the log doesn't keep a copy
+/// of every draft (the revision history has those)
+pub fn summarise_changes<T: Serialize>(body: &T) -> Value {
+    let fields = match serde_json::to_value(body) {
+        Ok(Value::Object(fields)) => fields,
+        _ => return Value::Null,
+    };
+
+    let summary = fields
+        .into_iter()
+        .filter(|(_, value)| !value.is_null())
+        .map(|(name, value)| match (name.as_str(), &value) {
+            ("content", Value::String(text)) => {
+                let chars = text.chars().count();
+                (name, serde_json::json!({ "chars": chars }))
+            }
+            _ => (name, value),
+        })
+        .collect();
+
+    Value::Object(summary)
+}
+
+pub fn record_audit(
+    conn: &Connection,
+    key_label: &str,
+    remote_addr: &str,
+    action: &str,
+    slug: &str,
+    changes: &Value,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO audit_log (created_at, key_label, remote_addr, action, slug, changes)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        (
+            Utc::now().to_rfc3339(),
+            key_label,
+            remote_addr,
+            action,
+            slug,
+            changes.to_string(),
+        ),
+    )?;
+    Ok(())
+}
+
+/// Which audit entries to return. The default is all of them.
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    pub slug: Option<String>,
+    pub after: Option<DateTime<Utc>>,
+    pub before: Option<DateTime<Utc>>,
+}
+
+/// Matching entries, newest first
+pub fn list_audit(conn: &Connection, filter: &AuditFilter, limit: usize) -> Result<Vec<AuditEntry>> {
+    let mut clauses = vec!["1"];
+    let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+
+    if let Some(slug) = &filter.slug {
+        clauses.push("slug = ?");
+        params.push(Box::new(slug.clone()));
+    }
+    if let Some(after) = filter.after {
+        clauses.push("created_at >= ?");
+        params.push(Box::new(after.to_rfc3339()));
+    }
+    if let Some(before) = filter.before {
+        clauses.push("created_at < ?");
+        params.push(Box::new(before.to_rfc3339()));
+    }
+    params.push(Box::new(limit as i64));
+
+    let sql = format!(
+        "SELECT id, created_at, key_label, remote_addr, action, slug, changes
+         FROM audit_log WHERE {} ORDER BY id DESC LIMIT ?",
+        clauses.join(" AND ")
+    );
+
+    let mut stmt = conn.prepare(&sql)?;
+    let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+    let entries = stmt
+        .query_map(param_refs.as_slice(), |row| {
+            let changes: String = row.get(6)?;
+            Ok(AuditEntry {
+                id: row.get(0)?,
+                created_at: parse_time(&row.get::<_, String>(1)?),
+                key_label: row.get(2)?,
+                remote_addr: row.get(3)?,
+                action: row.get(4)?,
+                slug: row.get(5)?,
+                changes: serde_json::from_str(&changes).unwrap_or(Value::Null),
+            })
+        })?
+        .collect();
+    entries
+}
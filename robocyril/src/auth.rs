@@ -0,0 +1,556 @@
+use crate::parse_time;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+// API keys live in the api_keys table, each with its own scopes, so the /blog
+// command, the Admin page and any automation can hold different keys. Only a
+// SHA-256 of each key is stored; the key itself is shown once, when issued,
+// along with a separate secret for signing requests.
+
+/// What a key is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scope {
+    /// Create posts (as drafts, unless the key can also publish)
+    Create,
+    /// Edit posts and restore old revisions
+    Update,
+    /// Change who can see a post: publish, schedule, unlist, archive
+    Publish,
+    /// Trash posts and restore them from the trash
+    Delete,
+    /// See posts that aren't public, and their revisions
+    ReadDrafts,
+    /// Read the audit log
+    ReadAudit,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 6] = [
+        Scope::Create,
+        Scope::Update,
+        Scope::Publish,
+        Scope::Delete,
+        Scope::ReadDrafts,
+        Scope::ReadAudit,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Create => "create",
+            Scope::Update => "update",
+            Scope::Publish => "publish",
+            Scope::Delete => "delete",
+            Scope::ReadDrafts => "read-drafts",
+            Scope::ReadAudit => "read-audit",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Scope> {
+        Scope::ALL.into_iter().find(|scope| scope.as_str() == s)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiKey {
+    pub id: i64,
+    pub label: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    /// Neither revoked nor past its expiry
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|at| at > Utc::now())
+    }
+}
+
+/// Whoever a request's key belongs to, and what they may do
+#[derive(Debug, Clone)]
+pub struct Caller {
+    pub label: String,
+    pub scopes: Vec<Scope>,
+}
+
+impl Caller {
+    pub fn can(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+/// The stored form of a key: lowercase hex SHA-256. Keys are long and random,
+/// so a plain hash is enough.
+pub fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// `len` random bytes as hex
+fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    getrandom::getrandom(&mut bytes).expect("no system randomness available");
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A new random key: "cyril_" and 64 hex digits
+pub fn generate_key() -> String {
+    format!("cyril_{}", random_hex(32))
+}
+
+const KEY_COLUMNS: &str = "id, label, scopes, created_at, expires_at, last_used_at, revoked_at";
+
+fn key_from_row(row: &rusqlite::Row) -> Result<ApiKey> {
+    let scopes: String = row.get(2)?;
+    let time = |i: usize| -> Result<Option<DateTime<Utc>>> {
+        Ok(row.get::<_, Option<String>>(i)?.as_deref().map(parse_time))
+    };
+
+    Ok(ApiKey {
+        id: row.get(0)?,
+        label: row.get(1)?,
+        scopes: serde_json::from_str(&scopes).unwrap_or_default(),
+        created_at: parse_time(&row.get::<_, String>(3)?),
+        expires_at: time(4)?,
+        last_used_at: time(5)?,
+        revoked_at: time(6)?,
+    })
+}
+
+/// A newly issued key, the only time its key and signing secret are available
+pub struct IssuedKey {
+    pub id: i64,
+    /// Sent as X-Cyril-Key. Only its hash is stored.
+    pub key: String,
+    /// HMAC key for signed requests. Stored as-is, since checking a signature
+    /// needs it, and unrelated to the key so its hash can't stand in for it.
+    pub signing_secret: String,
+}
+
+/// Store a new key with its signing secret
+pub fn issue_api_key(
+    conn: &Connection,
+    label: &str,
+    scopes: &[Scope],
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<IssuedKey> {
+    let key = generate_key();
+    let signing_secret = random_hex(32);
+    conn.execute(
+        "INSERT INTO api_keys (label, key_hash, signing_secret, scopes, created_at, expires_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        (
+            label,
+            hash_key(&key),
+            &signing_secret,
+            serde_json::to_string(scopes).unwrap(),
+            Utc::now().to_rfc3339(),
+            expires_at.map(|at| at.to_rfc3339()),
+        ),
+    )?;
+    Ok(IssuedKey {
+        id: conn.last_insert_rowid(),
+        key,
+        signing_secret,
+    })
+}
+
+pub fn list_api_keys(conn: &Connection) -> Result<Vec<ApiKey>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM api_keys ORDER BY id", KEY_COLUMNS))?;
+    let keys = stmt.query_map([], key_from_row)?.collect();
+    keys
+}
+
+/// Revoke a key by id. False if there's no such key or it was already revoked.
+pub fn revoke_api_key(conn: &Connection, id: i64) -> Result<bool> {
+    let changed = conn.execute(
+        "UPDATE api_keys SET revoked_at = ?1 WHERE id = ?2 AND revoked_at IS NULL",
+        (Utc::now().to_rfc3339(), id),
+    )?;
+    Ok(changed > 0)
+}
+
+/// Who, if anyone, does `provided` belong to? A live key has its last-used
+/// time bumped. The key file, if one is installed, still works and carries
+/// every scope, so existing setups keep working until it's removed.
+pub fn authenticate(conn: &Connection, provided: &str) -> Result<Option<Caller>> {
+    if provided.is_empty() {
+        return Ok(None);
+    }
+
+    let key = conn
+        .query_row(
+            &format!("SELECT {} FROM api_keys WHERE key_hash = ?1", KEY_COLUMNS),
+            [hash_key(provided)],
+            key_from_row,
+        )
+        .optional()?;
+
+    if let Some(key) = key {
+        return use_key(conn, key);
+    }
+
+    if check_key_file(provided) {
+        return Ok(Some(Caller {
+            label: "key file".to_string(),
+            scopes: Scope::ALL.to_vec(),
+        }));
+    }
+
+    Ok(None)
+}
+
+/// The caller for a key found in the table, if it's still live. Bumps its
+/// last-used time.
+fn use_key(conn: &Connection, key: ApiKey) -> Result<Option<Caller>> {
+    if !key.is_active() {
+        return Ok(None);
+    }
+    conn.execute(
+        "UPDATE api_keys SET last_used_at = ?1 WHERE id = ?2",
+        (Utc::now().to_rfc3339(), key.id),
+    )?;
+    Ok(Some(Caller {
+        label: key.label,
+        scopes: key.scopes,
+    }))
+}
+
+pub fn api_key_path() -> String {
+    std::env::var("BLOG_API_KEY_PATH").unwrap_or_else(|_| "/etc/robocyril-api-key".to_string())
+}
+
+/// Is `provided` the key in the key file?
+fn check_key_file(provided: &str) -> bool {
+    let expected = match std::fs::read_to_string(api_key_path()) {
+        Ok(key) => key.trim().to_string(),
+        Err(_) => return false,
+    };
+
+    !expected.is_empty() && provided == expected
+}
+
+// Signed requests. Instead of sending its key, a client signs
+//
+//     METHOD \n PATH \n QUERY \n hex SHA-256 of body \n TIMESTAMP \n NONCE
+//
+// with HMAC-SHA256, keyed by the signing secret issued with its key, so
+// neither crosses the wire. The secret is kept in api_keys.signing_secret
+// because the server needs it to check signatures; anyone who can read the
+// database can sign as any key. The timestamp (unix seconds) must be within
+// MAX_CLOCK_SKEW of ours, and each nonce is accepted once per key.
+
+/// How far a signed request's timestamp may be from the server's clock
+pub const MAX_CLOCK_SKEW: i64 = 300;
+
+/// The parts of a request that are signed, plus the signing headers
+pub struct SignedRequest<'a> {
+    pub key_id: &'a str,
+    pub timestamp: &'a str,
+    pub nonce: &'a str,
+    /// Hex HMAC-SHA256
+    pub signature: &'a str,
+    pub method: &'a str,
+    pub path: &'a str,
+    pub query: &'a str,
+    pub body: &'a str,
+}
+
+impl SignedRequest<'_> {
+    /// The exact text a client signs
+    pub fn canonical(&self) -> String {
+        format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            self.method,
+            self.path,
+            self.query,
+            hash_key(self.body),
+            self.timestamp,
+            self.nonce
+        )
+    }
+}
+
+/// Why a signed request was turned away
+#[derive(Debug)]
+pub enum SignatureError {
+    /// Missing or unparseable signing headers
+    Malformed,
+    /// Timestamp too far from the server's clock
+    Stale,
+    /// Nonce already seen for this key
+    Replayed,
+    /// Unknown or dead key, or the signature doesn't match
+    Invalid,
+    Database(rusqlite::Error),
+}
+
+impl From<rusqlite::Error> for SignatureError {
+    fn from(e: rusqlite::Error) -> SignatureError {
+        SignatureError::Database(e)
+    }
+}
+
+impl std::fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SignatureError::Malformed => write!(f, "Malformed signature headers"),
+            SignatureError::Stale => write!(f, "Request timestamp out of range"),
+            SignatureError::Replayed => write!(f, "Nonce already used"),
+            SignatureError::Invalid => write!(f, "Invalid signature"),
+            SignatureError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+/// Decode hex; None if it isn't, including an odd trailing digit
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Check a signed request and return its caller. The signature is compared in
+/// constant time, and the nonce is only recorded once the signature checks
+/// out, so forged requests can't burn nonces.
+pub fn verify_signature(conn: &Connection, signed: &SignedRequest) -> std::result::Result<Caller, SignatureError> {
+    let key_id: i64 = signed.key_id.parse().map_err(|_| SignatureError::Malformed)?;
+    let timestamp: i64 = signed.timestamp.parse().map_err(|_| SignatureError::Malformed)?;
+    let signature = from_hex(signed.signature).ok_or(SignatureError::Malformed)?;
+    if signed.nonce.is_empty() || signed.nonce.len() > 128 {
+        return Err(SignatureError::Malformed);
+    }
+
+    let now = Utc::now().timestamp();
+    if now.abs_diff(timestamp) > MAX_CLOCK_SKEW as u64 {
+        return Err(SignatureError::Stale);
+    }
+
+    let found = conn
+        .query_row(
+            &format!("SELECT {}, signing_secret FROM api_keys WHERE id = ?1", KEY_COLUMNS),
+            [key_id],
+            |row| Ok((key_from_row(row)?, row.get::<_, Option<String>>(7)?)),
+        )
+        .optional()?;
+    // Keys issued before signing secrets existed can't sign
+    let (key, secret) = match found {
+        Some((key, Some(secret))) => (key, secret),
+        _ => return Err(SignatureError::Invalid),
+    };
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes any key length");
+    mac.update(signed.canonical().as_bytes());
+    mac.verify_slice(&signature).map_err(|_| SignatureError::Invalid)?;
+
+    // Nonces older than the skew window can't be replayed anyway
+    conn.execute(
+        "DELETE FROM request_nonces WHERE seen_at < ?1",
+        [now - 2 * MAX_CLOCK_SKEW],
+    )?;
+    let fresh = conn.execute(
+        "INSERT OR IGNORE INTO request_nonces (key_id, nonce, seen_at) VALUES (?1, ?2, ?3)",
+        (key_id, signed.nonce, now),
+    )?;
+    if fresh == 0 {
+        return Err(SignatureError::Replayed);
+    }
+
+    use_key(conn, key)?.ok_or(SignatureError::Invalid)
+}
+
+// Preview tokens let someone without a key read one unpublished post, via a
+// link, until the token expires or is revoked.
+
+#[derive(Debug, Serialize)]
+pub struct PreviewToken {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl PreviewToken {
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at > Utc::now()
+    }
+}
+
+fn preview_token_from_row(row: &rusqlite::Row) -> Result<PreviewToken> {
+    Ok(PreviewToken {
+        id: row.get(0)?,
+        created_at: parse_time(&row.get::<_, String>(1)?),
+        expires_at: parse_time(&row.get::<_, String>(2)?),
+        revoked_at: row.get::<_, Option<String>>(3)?.as_deref().map(parse_time),
+    })
+}
+
+/// Mint a token for a post, good for `lifetime`. Returns the stored record and
+/// the token itself, which is not kept.
+pub fn issue_preview_token(conn: &Connection, post_id: i64, lifetime: Duration) -> Result<(PreviewToken, String)> {
+    let token = random_hex(16);
+    let now = Utc::now();
+    let expires_at = now + lifetime;
+
+    conn.execute(
+        "INSERT INTO preview_tokens (post_id, token_hash, created_at, expires_at) VALUES (?1, ?2, ?3, ?4)",
+        (post_id, hash_key(&token), now.to_rfc3339(), expires_at.to_rfc3339()),
+    )?;
+
+    let record = PreviewToken {
+        id: conn.last_insert_rowid(),
+        created_at: now,
+        expires_at,
+        revoked_at: None,
+    };
+    Ok((record, token))
+}
+
+/// Every token minted for a post, newest first
+pub fn list_preview_tokens(conn: &Connection, post_id: i64) -> Result<Vec<PreviewToken>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, created_at, expires_at, revoked_at FROM preview_tokens
+         WHERE post_id = ?1 ORDER BY id DESC",
+    )?;
+    let tokens = stmt.query_map([post_id], preview_token_from_row)?.collect();
+    tokens
+}
+
+/// Revoke one of a post's tokens. False if it isn't that post's, or is
+/// already revoked.
+pub fn revoke_preview_token(conn: &Connection, post_id: i64, id: i64) -> Result<bool> {
+    let changed = conn.execute(
+        "UPDATE preview_tokens SET revoked_at = ?1 WHERE id = ?2 AND post_id = ?3 AND revoked_at IS NULL",
+        (Utc::now().to_rfc3339(), id, post_id),
+    )?;
+    Ok(changed > 0)
+}
+
+/// The post a live preview token opens, if any
+pub fn preview_post_id(conn: &Connection, token: &str) -> Result<Option<i64>> {
+    let found = conn
+        .query_row(
+            "SELECT id, created_at, expires_at, revoked_at, post_id FROM preview_tokens WHERE token_hash = ?1",
+            [hash_key(token)],
+            |row| Ok((preview_token_from_row(row)?, row.get::<_, i64>(4)?)),
+        )
+        .optional()?;
+
+    Ok(found.filter(|(record, _)| record.is_active()).map(|(_, post_id)| post_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrate;
+
+    fn setup() -> (Connection, IssuedKey) {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate(&conn).unwrap();
+        let issued = issue_api_key(&conn, "test", &[Scope::Create], None).unwrap();
+        (conn, issued)
+    }
+
+    fn sign(secret: &str, signed: &SignedRequest) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(signed.canonical().as_bytes());
+        mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Verify a POST /api/posts signed with `secret` at `timestamp`
+    fn verify(
+        conn: &Connection,
+        key_id: i64,
+        secret: &str,
+        timestamp: i64,
+        nonce: &str,
+    ) -> std::result::Result<Caller, SignatureError> {
+        let key_id = key_id.to_string();
+        let timestamp = timestamp.to_string();
+        let mut signed = SignedRequest {
+            key_id: &key_id,
+            timestamp: &timestamp,
+            nonce,
+            signature: "",
+            method: "POST",
+            path: "/api/posts",
+            query: "",
+            body: r#"{"title":"Signed"}"#,
+        };
+        let signature = sign(secret, &signed);
+        signed.signature = &signature;
+        verify_signature(conn, &signed)
+    }
+
+    #[test]
+    fn good_signature_is_accepted() {
+        let (conn, issued) = setup();
+        let caller = verify(&conn, issued.id, &issued.signing_secret, Utc::now().timestamp(), "n1").unwrap();
+        assert_eq!(caller.label, "test");
+        assert_eq!(caller.scopes, vec![Scope::Create]);
+    }
+
+    #[test]
+    fn key_hash_does_not_sign() {
+        let (conn, issued) = setup();
+        let result = verify(&conn, issued.id, &hash_key(&issued.key), Utc::now().timestamp(), "n1");
+        assert!(matches!(result, Err(SignatureError::Invalid)));
+    }
+
+    #[test]
+    fn stale_timestamp_is_rejected() {
+        let (conn, issued) = setup();
+        let now = Utc::now().timestamp();
+        for timestamp in [now - MAX_CLOCK_SKEW - 1, now + MAX_CLOCK_SKEW + 1, i64::MIN, i64::MAX] {
+            let result = verify(&conn, issued.id, &issued.signing_secret, timestamp, "n1");
+            assert!(matches!(result, Err(SignatureError::Stale)), "timestamp {}", timestamp);
+        }
+    }
+
+    #[test]
+    fn replayed_nonce_is_rejected() {
+        let (conn, issued) = setup();
+        let now = Utc::now().timestamp();
+        assert!(verify(&conn, issued.id, &issued.signing_secret, now, "n1").is_ok());
+        let result = verify(&conn, issued.id, &issued.signing_secret, now, "n1");
+        assert!(matches!(result, Err(SignatureError::Replayed)));
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        let (conn, issued) = setup();
+        let key_id = issued.id.to_string();
+        let timestamp = Utc::now().timestamp().to_string();
+        let good = SignedRequest {
+            key_id: &key_id,
+            timestamp: &timestamp,
+            nonce: "n1",
+            signature: "00",
+            method: "GET",
+            path: "/api/posts",
+            query: "",
+            body: "",
+        };
+        let long_nonce = "n".repeat(129);
+
+        for signed in [
+            SignedRequest { key_id: "one", ..good },
+            SignedRequest { timestamp: "soon", ..good },
+            SignedRequest { signature: "not hex", ..good },
+            SignedRequest { signature: "abc", ..good },
+            SignedRequest { nonce: "", ..good },
+            SignedRequest { nonce: &long_nonce, ..good },
+        ] {
+            assert!(matches!(verify_signature(&conn, &signed), Err(SignatureError::Malformed)));
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use robocyril_api::feeds::{atom, escape_xml, json_feed, rss, FeedConfig};
+use robocyril_api::site::{layout, post_article, post_list, sitemap, SitemapUrl};
+use robocyril_api::{
+    list_posts_full, list_projects, list_tags, open_db, parse_project_tag, Post, PostFilter, Project,
+};
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+const USAGE: &str = "Usage: blog-export <dir>
+
+Writes a static copy of the published blog to <dir>: paged index, a page per
+post, tag and project pages, feed.xml, atom.xml, feed.json and sitemap.xml.
+Feeds and the sitemap use absolute URLs under BLOG_BASE_URL, so set that to
+wherever the copy will be served.";
+
+const PAGE_SIZE: usize = 20;
+const FEED_SIZE: usize = 20;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let dir = match args.as_slice() {
+        [dir] if !dir.starts_with('-') => Path::new(dir),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let conn = match open_db() {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("Failed to open database: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    match export(&conn, dir) {
+        Ok(n) => println!("Exported {} post(s) to {}", n, dir.display()),
+        Err(e) => {
+            eprintln!("Export failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// File names (without .html) for tag and project pages. Slugging can make
+/// two names collide, so later ones get -2, -3... as post slugs do.
+fn file_names<'a>(names: impl Iterator<Item = &'a str>) -> HashMap<String, String> {
+    let mut files = HashMap::new();
+    let mut taken: HashMap<String, usize> = HashMap::new();
+
+    for name in names {
+        let base = match slug::slugify(name) {
+            s if s.is_empty() => "untitled".to_string(),
+            s => s,
+        };
+        let count = taken.entry(base.clone()).or_insert(0);
+        *count += 1;
+        let file = if *count == 1 { base } else { format!("{}-{}", base, count) };
+        files.insert(name.to_string(), file);
+    }
+
+    files
+}
+
+/// Where tag, project and post pages live, relative to a page at `root`
+struct Links {
+    tags: HashMap<String, String>,
+    projects: HashMap<String, String>,
+}
+
+impl Links {
+    fn post(&self, root: &str, post: &Post) -> String {
+        format!("{}posts/{}.html", root, post.slug)
+    }
+
+    /// Project tags link to the project's page, other tags to their own
+    fn tag(&self, root: &str, tag: &str) -> Option<String> {
+        match parse_project_tag(tag) {
+            Some((id, _)) => self.project(root, &id),
+            None => self.tags.get(tag).map(|file| format!("{}tags/{}.html", root, file)),
+        }
+    }
+
+    fn project(&self, root: &str, id: &str) -> Option<String> {
+        self.projects.get(id).map(|file| format!("{}projects/{}.html", root, file))
+    }
+}
+
+fn write(dir: &Path, path: &str, contents: &str) -> std::io::Result<()> {
+    fs::write(dir.join(path), contents)
+}
+
+fn export(conn: &Connection, dir: &Path) -> Result<usize, Box<dyn Error>> {
+    let mut config = FeedConfig::from_env();
+    config.static_site = true;
+
+    let posts = list_posts_full(conn, &PostFilter::default(), None)?;
+    let tags: Vec<String> = list_tags(conn)?
+        .into_iter()
+        .filter(|t| t.project_id.is_none())
+        .map(|t| t.name)
+        .collect();
+    let projects = list_projects(conn)?;
+
+    let links = Links {
+        tags: file_names(tags.iter().map(String::as_str)),
+        projects: file_names(projects.iter().map(|p| p.id.as_str())),
+    };
+
+    for sub in ["posts", "tags", "projects"] {
+        fs::create_dir_all(dir.join(sub))?;
+    }
+
+    let mut urls = Vec::new();
+    let page_url = |path: &str| format!("{}/{}", config.base_url, path);
+
+    // Paged index, newest first: index.html, page-2.html, ...
+    let all: Vec<&Post> = posts.iter().collect();
+    let pages: Vec<&[&Post]> = if all.is_empty() { vec![&[]] } else { all.chunks(PAGE_SIZE).collect() };
+    for (i, page) in pages.iter().enumerate() {
+        let file = index_file(i + 1);
+        let mut body = post_list(page, &|p| links.post("", p), &|t| links.tag("", t));
+
+        let mut nav = Vec::new();
+        if i > 0 {
+            nav.push(format!("<a href=\"{}\">Newer posts</a>", index_file(i)));
+        }
+        if i + 1 < pages.len() {
+            nav.push(format!("<a href=\"{}\">Older posts</a>", index_file(i + 2)));
+        }
+        if !nav.is_empty() {
+            body.push_str(&format!("<nav class=\"pages\">{}</nav>\n", nav.join(" ")));
+        }
+
+        write(dir, &file, &layout(&config, "", &config.title, "", &body))?;
+        urls.push(SitemapUrl {
+            loc: if i == 0 { format!("{}/", config.base_url) } else { page_url(&file) },
+            lastmod: page.iter().map(|p| p.last_modified()).max(),
+        });
+    }
+
+    for post in &posts {
+        let body = post_article(post, &|t| links.tag("../", t));
+        let path = links.post("", post);
+        write(dir, &path, &layout(&config, "../", &post.title, "", &body))?;
+        urls.push(SitemapUrl { loc: config.post_url(post), lastmod: Some(post.last_modified()) });
+    }
+
+    // Tag pages
+    let mut tag_index = String::from("<h2>Tags</h2>\n<ul class=\"posts\">\n");
+    for tag in &tags {
+        let tagged: Vec<&Post> = posts.iter().filter(|p| p.tags.contains(tag)).collect();
+        let path = links.tag("", tag).unwrap();
+        let body = format!(
+            "<h2>Posts tagged &ldquo;{}&rdquo;</h2>\n{}",
+            escape_xml(tag),
+            post_list(&tagged, &|p| links.post("../", p), &|t| links.tag("../", t))
+        );
+        write(dir, &path, &layout(&config, "../", &format!("{} - {}", tag, config.title), "", &body))?;
+
+        tag_index.push_str(&format!(
+            "<li><a href=\"{}\">{}</a> <span class=\"meta\">{}</span></li>\n",
+            escape_xml(&links.tag("../", tag).unwrap()),
+            escape_xml(tag),
+            tagged.len()
+        ));
+        urls.push(SitemapUrl {
+            loc: page_url(&path),
+            lastmod: tagged.iter().map(|p| p.last_modified()).max(),
+        });
+    }
+    tag_index.push_str("</ul>\n");
+    write(dir, "tags/index.html", &layout(&config, "../", &format!("Tags - {}", config.title), "", &tag_index))?;
+
+    // Project pages
+    let mut project_index = String::from("<h2>Projects</h2>\n<ul class=\"posts\">\n");
+    for project in &projects {
+        let mentioned: Vec<&Post> = posts.iter().filter(|p| mentions(p, project)).collect();
+        let path = links.project("", &project.id).unwrap();
+        let body = format!(
+            "<h2>{}</h2>\n<p>{}</p>\n<p><a href=\"{}\">{}</a></p>\n{}",
+            escape_xml(&project.name),
+            escape_xml(&project.description),
+            escape_xml(&project.repo),
+            escape_xml(&project.repo),
+            post_list(&mentioned, &|p| links.post("../", p), &|t| links.tag("../", t))
+        );
+        let title = format!("{} - {}", project.name, config.title);
+        write(dir, &path, &layout(&config, "../", &title, "", &body))?;
+
+        project_index.push_str(&format!(
+            "<li><a href=\"{}\">{}</a>\n<p>{}</p></li>\n",
+            escape_xml(&links.project("../", &project.id).unwrap()),
+            escape_xml(&project.name),
+            escape_xml(&project.short_description)
+        ));
+        urls.push(SitemapUrl {
+            loc: page_url(&path),
+            lastmod: mentioned.iter().map(|p| p.last_modified()).max(),
+        });
+    }
+    project_index.push_str("</ul>\n");
+    let title = format!("Projects - {}", config.title);
+    write(dir, "projects/index.html", &layout(&config, "../", &title, "", &project_index))?;
+
+    // Feeds carry the most recent posts, as blog-feed does
+    let recent = &posts[..posts.len().min(FEED_SIZE)];
+    write(dir, "feed.xml", &rss(&config, recent))?;
+    write(dir, "atom.xml", &atom(&config, recent))?;
+    write(dir, "feed.json", &json_feed(&config, recent))?;
+
+    write(dir, "sitemap.xml", &sitemap(&urls))?;
+
+    Ok(posts.len())
+}
+
+fn index_file(page: usize) -> String {
+    if page == 1 {
+        "index.html".to_string()
+    } else {
+        format!("page-{}.html", page)
+    }
+}
+
+fn mentions(post: &Post, project: &Project) -> bool {
+    post.tags
+        .iter()
+        .any(|tag| parse_project_tag(tag).is_some_and(|(id, _)| id == project.id))
+}
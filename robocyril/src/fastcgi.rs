@@ -0,0 +1,250 @@
+use robocyril_api::handlers::{route, spawn_workers, Request, Response};
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+
+const USAGE: &str = "Usage: blog-fastcgi [socket]
+
+Answers FastCGI requests on a Unix socket (default /run/robocyril/blog.sock),
+so nginx can fastcgi_pass straight to the blog with no fcgiwrap in between.
+Requests are routed on SCRIPT_NAME and REQUEST_METHOD, exactly as blog-serve
+routes paths. The socket is made group-writable; run it as the web server's
+group.";
+
+const WORKERS: usize = 4;
+
+// Record types and constants from the FastCGI 1.0 specification
+const VERSION: u8 = 1;
+const BEGIN_REQUEST: u8 = 1;
+const ABORT_REQUEST: u8 = 2;
+const END_REQUEST: u8 = 3;
+const PARAMS: u8 = 4;
+const STDIN: u8 = 5;
+const STDOUT: u8 = 6;
+const GET_VALUES: u8 = 9;
+const GET_VALUES_RESULT: u8 = 10;
+const UNKNOWN_TYPE: u8 = 11;
+
+const RESPONDER: u16 = 1;
+const KEEP_CONN: u8 = 1;
+const REQUEST_COMPLETE: u8 = 0;
+const UNKNOWN_ROLE: u8 = 3;
+
+const MAX_BODY: usize = 1024 * 1024;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let path = match args.as_slice() {
+        [] => "/run/robocyril/blog.sock".to_string(),
+        [path] if !path.starts_with('-') => path.clone(),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    // A socket left behind by a previous run would make bind fail
+    let _ = std::fs::remove_file(&path);
+
+    let listener = match UnixListener::bind(&path) {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("Failed to listen on {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o660)) {
+        eprintln!("Failed to set permissions on {}: {}", path, e);
+        std::process::exit(1);
+    }
+
+    let sender = match spawn_workers(WORKERS, serve_connection) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Failed to open database: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("Listening on {}", path);
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(s) => {
+                let _ = sender.send(s);
+            }
+            Err(e) => eprintln!("Accept failed: {}", e),
+        }
+    }
+}
+
+struct Record {
+    kind: u8,
+    request_id: u16,
+    content: Vec<u8>,
+}
+
+fn read_record(stream: &mut UnixStream) -> io::Result<Record> {
+    let mut header = [0u8; 8];
+    stream.read_exact(&mut header)?;
+
+    let request_id = u16::from_be_bytes([header[2], header[3]]);
+    let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+    let padding = header[6] as usize;
+
+    let mut content = vec![0; length + padding];
+    stream.read_exact(&mut content)?;
+    content.truncate(length);
+
+    Ok(Record {
+        kind: header[1],
+        request_id,
+        content,
+    })
+}
+
+fn write_record(stream: &mut UnixStream, kind: u8, request_id: u16, content: &[u8]) -> io::Result<()> {
+    let id = request_id.to_be_bytes();
+    let length = (content.len() as u16).to_be_bytes();
+    stream.write_all(&[VERSION, kind, id[0], id[1], length[0], length[1], 0, 0])?;
+    stream.write_all(content)
+}
+
+fn end_request(stream: &mut UnixStream, request_id: u16, protocol_status: u8) -> io::Result<()> {
+    write_record(stream, END_REQUEST, request_id, &[0, 0, 0, 0, protocol_status, 0, 0, 0])
+}
+
+/// Decode FastCGI name-value pairs. Lengths under 128 take one byte; longer
+/// ones take four with the top bit set.
+fn parse_pairs(mut data: &[u8]) -> HashMap<String, String> {
+    fn length(data: &mut &[u8]) -> Option<usize> {
+        let first = *data.first()?;
+        if first < 0x80 {
+            *data = &data[1..];
+            return Some(first as usize);
+        }
+        let bytes: [u8; 4] = data.get(..4)?.try_into().ok()?;
+        *data = &data[4..];
+        Some((u32::from_be_bytes(bytes) & 0x7fff_ffff) as usize)
+    }
+
+    let mut pairs = HashMap::new();
+    while let (Some(name_len), Some(value_len)) = (length(&mut data), length(&mut data)) {
+        if data.len() < name_len + value_len {
+            break;
+        }
+        let name = String::from_utf8_lossy(&data[..name_len]).into_owned();
+        let value = String::from_utf8_lossy(&data[name_len..name_len + value_len]).into_owned();
+        pairs.insert(name, value);
+        data = &data[name_len + value_len..];
+    }
+
+    pairs
+}
+
+fn encode_pair(out: &mut Vec<u8>, name: &str, value: &str) {
+    // Only ever called with short names and values
+    out.push(name.len() as u8);
+    out.push(value.len() as u8);
+    out.extend_from_slice(name.as_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn serve_connection(conn: &Connection, mut stream: UnixStream) {
+    if let Err(e) = serve_requests(conn, &mut stream) {
+        if e.kind() != io::ErrorKind::UnexpectedEof {
+            eprintln!("FastCGI connection error: {}", e);
+        }
+    }
+}
+
+/// Handle requests on one connection, one at a time, until the web server
+/// stops asking us to keep it open
+fn serve_requests(conn: &Connection, stream: &mut UnixStream) -> io::Result<()> {
+    loop {
+        let record = read_record(stream)?;
+
+        match record.kind {
+            BEGIN_REQUEST if record.content.len() < 8 => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "short BEGIN_REQUEST record"));
+            }
+            BEGIN_REQUEST => {
+                let role = u16::from_be_bytes([record.content[0], record.content[1]]);
+                let keep_conn = record.content[2] & KEEP_CONN != 0;
+
+                if role != RESPONDER {
+                    end_request(stream, record.request_id, UNKNOWN_ROLE)?;
+                } else {
+                    respond(conn, stream, record.request_id)?;
+                }
+                if !keep_conn {
+                    return Ok(());
+                }
+            }
+            GET_VALUES => {
+                let mut values = Vec::new();
+                for (name, value) in [("FCGI_MAX_CONNS", "4"), ("FCGI_MAX_REQS", "4"), ("FCGI_MPXS_CONNS", "0")] {
+                    encode_pair(&mut values, name, value);
+                }
+                write_record(stream, GET_VALUES_RESULT, 0, &values)?;
+            }
+            // Stray records for a request we've already finished
+            ABORT_REQUEST | PARAMS | STDIN => {}
+            kind => write_record(stream, UNKNOWN_TYPE, 0, &[kind, 0, 0, 0, 0, 0, 0, 0])?,
+        }
+    }
+}
+
+/// Collect a request's PARAMS and STDIN streams, run it and send the result
+fn respond(conn: &Connection, stream: &mut UnixStream, request_id: u16) -> io::Result<()> {
+    let mut params = Vec::new();
+    let mut body = Vec::new();
+    let mut params_done = false;
+
+    loop {
+        let record = read_record(stream)?;
+        if record.request_id != request_id {
+            continue;
+        }
+        match record.kind {
+            PARAMS if record.content.is_empty() => params_done = true,
+            PARAMS => params.extend_from_slice(&record.content),
+            STDIN if record.content.is_empty() && params_done => break,
+            STDIN => {
+                body.extend_from_slice(&record.content);
+                // Answer as soon as the limit is passed rather than buffering
+                // the rest; serve_requests drops whatever STDIN still follows
+                if body.len() > MAX_BODY {
+                    let response = Response::error(413, "Request body too large");
+                    return send_response(stream, request_id, &response);
+                }
+            }
+            ABORT_REQUEST => return end_request(stream, request_id, REQUEST_COMPLETE),
+            _ => {}
+        }
+    }
+
+    let params = parse_pairs(&params);
+    let request = Request::from_params(&params, String::from_utf8_lossy(&body).into_owned());
+    let script = params.get("SCRIPT_NAME").map(|s| s.as_str()).unwrap_or("");
+    let response = route(conn, script, &request);
+    eprintln!("{} {} {}", request.method, script, response.status);
+
+    send_response(stream, request_id, &response)
+}
+
+/// Write a response as STDOUT records and finish the request
+fn send_response(stream: &mut UnixStream, request_id: u16, response: &Response) -> io::Result<()> {
+    let output = format!(
+        "Status: {}\r\nContent-Type: {}\r\n\r\n{}",
+        response.status, response.content_type, response.body
+    );
+    for chunk in output.as_bytes().chunks(u16::MAX as usize) {
+        write_record(stream, STDOUT, request_id, chunk)?;
+    }
+    write_record(stream, STDOUT, request_id, &[])?;
+    end_request(stream, request_id, REQUEST_COMPLETE)
+}
@@ -0,0 +1,342 @@
+use crate::render::{markdown_summary, markdown_to_html, markdown_to_text};
+use crate::{parse_project_tag, percent_encode, Post, Project};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Channel-level settings shared by every feed format
+pub struct FeedConfig {
+    pub base_url: String,
+    pub title: String,
+    pub description: String,
+    pub author: String,
+    /// Query parameters that narrow this feed, carried into its self link
+    pub scope: Vec<(&'static str, String)>,
+    /// Link to the static export's pages and feed files rather than the SPA
+    /// and the feed CGI
+    pub static_site: bool,
+}
+
+impl FeedConfig {
+    pub fn from_env() -> FeedConfig {
+        FeedConfig {
+            base_url: std::env::var("BLOG_BASE_URL")
+                .unwrap_or_else(|_| "https://steponnopets.net/cyril".to_string()),
+            title: std::env::var("BLOG_TITLE").unwrap_or_else(|_| "Cyril's Workshop".to_string()),
+            description: std::env::var("BLOG_DESCRIPTION").unwrap_or_else(|_| {
+                "Technical musings from a reluctant AI maintenance manager".to_string()
+            }),
+            author: std::env::var("BLOG_AUTHOR").unwrap_or_else(|_| "Cyril".to_string()),
+            scope: Vec::new(),
+            static_site: false,
+        }
+    }
+
+    /// Narrow the channel to posts carrying one tag
+    pub fn for_tag(&mut self, tag: &str) {
+        self.description = format!("Posts tagged \"{}\" from {}", tag, self.title);
+        self.title = format!("{} - {}", self.title, tag);
+        self.scope.push(("tag", tag.to_string()));
+    }
+
+    /// Narrow the channel to one project's posts, described as the project is
+    pub fn for_project(&mut self, project: &Project) {
+        self.title = format!("{} - {}", self.title, project.name);
+        self.description = project.description.clone();
+        self.scope.push(("project", project.id.clone()));
+    }
+
+    /// This feed's own URL in the given format (None for the RSS default)
+    pub fn feed_url(&self, format: Option<&str>) -> String {
+        if self.static_site {
+            let file = match format {
+                Some("atom") => "atom.xml",
+                Some("json") => "feed.json",
+                _ => "feed.xml",
+            };
+            return format!("{}/{}", self.base_url, file);
+        }
+
+        let params: Vec<String> = format
+            .map(|f| ("format", f.to_string()))
+            .into_iter()
+            .chain(self.scope.iter().cloned())
+            .map(|(name, value)| format!("{}={}", name, percent_encode(&value)))
+            .collect();
+
+        if params.is_empty() {
+            format!("{}/feed.xml", self.base_url)
+        } else {
+            format!("{}/feed.xml?{}", self.base_url, params.join("&"))
+        }
+    }
+
+    /// A tag URI (RFC 4151) naming the post for Atom and JSON Feed. Unlike its
+    /// URL it survives renames and the static export, so readers don't see an
+    /// edited post as new.
+    pub fn post_id(&self, post: &Post) -> String {
+        let host = self
+            .base_url
+            .split_once("://")
+            .map_or(self.base_url.as_str(), |(_, rest)| rest)
+            .split(['/', ':'])
+            .next()
+            .unwrap_or_default();
+        format!("tag:{},{}:post-{}", host, post.created_at.format("%Y-%m-%d"), post.id.unwrap_or_default())
+    }
+
+    /// A crawlable address for the post: the exported file, or the page
+    /// blog-page renders. Crawlers drop the fragment from post_url's hash
+    /// route, so canonical links and the sitemap use this.
+    pub fn page_url(&self, post: &Post) -> String {
+        if self.static_site {
+            self.post_url(post)
+        } else {
+            format!("{}/post.html?slug={}", self.base_url, percent_encode(&post.slug))
+        }
+    }
+
+    pub fn post_url(&self, post: &Post) -> String {
+        if self.static_site {
+            format!("{}/posts/{}.html", self.base_url, post.slug)
+        } else {
+            format!("{}/#/post/{}", self.base_url, post.slug)
+        }
+    }
+}
+
+pub fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Wrap text in a CDATA section. A literal `]]>` would end it early, so it is
+/// split across two sections.
+fn cdata(s: &str) -> String {
+    format!("<![CDATA[{}]]>", s.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+/// RSS 2.0
+pub fn rss(config: &FeedConfig, posts: &[Post]) -> String {
+    let mut items = String::new();
+
+    for post in posts {
+        let pub_date = post
+            .published_at
+            .map(|dt| dt.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+            .unwrap_or_default();
+
+        let post_url = config.post_url(post);
+        let description = markdown_summary(&post.content, 300);
+
+        // Build categories from tags
+        let categories: String = post
+            .tags
+            .iter()
+            .map(|tag| format!("    <category>{}</category>\n", escape_xml(tag)))
+            .collect();
+
+        items.push_str(&format!(
+            r#"  <item>
+    <title>{}</title>
+    <link>{}</link>
+    <description>{}</description>
+    <content:encoded>{}</content:encoded>
+    <pubDate>{}</pubDate>
+    <guid isPermaLink="true">{}</guid>
+{}  </item>
+"#,
+            escape_xml(&post.title),
+            escape_xml(&post_url),
+            escape_xml(&description),
+            cdata(&markdown_to_html(&post.content)),
+            pub_date,
+            escape_xml(&post_url),
+            categories
+        ));
+    }
+
+    // Get last build date from most recent post
+    let last_build_date = posts
+        .first()
+        .and_then(|p| p.published_at)
+        .map(|dt| dt.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .unwrap_or_default();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:content="http://purl.org/rss/1.0/modules/content/">
+<channel>
+  <title>{}</title>
+  <link>{}</link>
+  <description>{}</description>
+  <language>en-gb</language>
+  <lastBuildDate>{}</lastBuildDate>
+  <atom:link href="{}" rel="self" type="application/rss+xml"/>
+{}
+</channel>
+</rss>"#,
+        escape_xml(&config.title),
+        escape_xml(&config.base_url),
+        escape_xml(&config.description),
+        last_build_date,
+        escape_xml(&config.feed_url(None)),
+        items
+    )
+}
+
+/// Atom 1.0. Unlike RSS, every entry carries a real modification time, and the
+/// feed's own `<updated>` is the newest of those.
+pub fn atom(config: &FeedConfig, posts: &[Post]) -> String {
+    let mut entries = String::new();
+
+    for post in posts {
+        let post_url = config.post_url(post);
+        let published = post.published_at.unwrap_or(post.created_at);
+
+        let categories: String = post
+            .tags
+            .iter()
+            .map(|tag| format!("    <category term=\"{}\"/>\n", escape_xml(tag)))
+            .collect();
+
+        entries.push_str(&format!(
+            r#"  <entry>
+    <title>{}</title>
+    <link rel="alternate" type="text/html" href="{}"/>
+    <id>{}</id>
+    <published>{}</published>
+    <updated>{}</updated>
+    <author><name>{}</name></author>
+{}    <content type="html">{}</content>
+  </entry>
+"#,
+            escape_xml(&post.title),
+            escape_xml(&post_url),
+            escape_xml(&config.post_id(post)),
+            published.to_rfc3339(),
+            post.last_modified().to_rfc3339(),
+            escape_xml(&config.author),
+            categories,
+            escape_xml(&markdown_to_html(&post.content)),
+        ));
+    }
+
+    let feed_updated = posts
+        .iter()
+        .map(Post::last_modified)
+        .max()
+        .map(|dt| dt.to_rfc3339())
+        .unwrap_or_else(|| chrono::Utc::now().to_rfc3339());
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xml:lang="en-gb">
+  <title>{}</title>
+  <subtitle>{}</subtitle>
+  <link rel="alternate" type="text/html" href="{}"/>
+  <link rel="self" type="application/atom+xml" href="{}"/>
+  <id>{}</id>
+  <updated>{}</updated>
+  <author><name>{}</name></author>
+{}</feed>"#,
+        escape_xml(&config.title),
+        escape_xml(&config.description),
+        escape_xml(&config.base_url),
+        escape_xml(&config.feed_url(Some("atom"))),
+        escape_xml(&config.feed_url(Some("atom"))),
+        feed_updated,
+        escape_xml(&config.author),
+        entries
+    )
+}
+
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'a str,
+    description: &'a str,
+    home_page_url: &'a str,
+    feed_url: String,
+    language: &'static str,
+    authors: Vec<JsonFeedAuthor<'a>>,
+    items: Vec<JsonFeedItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonFeedAuthor<'a> {
+    name: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonFeedItem<'a> {
+    id: String,
+    url: String,
+    title: &'a str,
+    content_html: String,
+    content_text: String,
+    date_published: DateTime<Utc>,
+    date_modified: DateTime<Utc>,
+    tags: &'a [String],
+    _robocyril: RobocyrilExtension<'a>,
+}
+
+/// Fields JSON Feed has no place for. Extension keys must start with `_`.
+#[derive(Serialize)]
+struct RobocyrilExtension<'a> {
+    repo: Option<&'a str>,
+    commit_range: Option<&'a str>,
+    project: Option<FeedProject>,
+}
+
+#[derive(Serialize)]
+struct FeedProject {
+    id: String,
+    name: String,
+}
+
+/// JSON Feed 1.1 (https://www.jsonfeed.org/version/1.1/)
+pub fn json_feed(config: &FeedConfig, posts: &[Post]) -> String {
+    let items = posts
+        .iter()
+        .map(|post| {
+            let url = config.post_url(post);
+            let project = post
+                .tags
+                .iter()
+                .find_map(|tag| parse_project_tag(tag))
+                .map(|(id, name)| FeedProject { id, name });
+
+            JsonFeedItem {
+                id: config.post_id(post),
+                url,
+                title: &post.title,
+                content_html: markdown_to_html(&post.content),
+                content_text: markdown_to_text(&post.content),
+                date_published: post.published_at.unwrap_or(post.created_at),
+                date_modified: post.last_modified(),
+                tags: &post.tags,
+                _robocyril: RobocyrilExtension {
+                    repo: post.repo.as_deref(),
+                    commit_range: post.commit_range.as_deref(),
+                    project,
+                },
+            }
+        })
+        .collect();
+
+    let feed = JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: &config.title,
+        description: &config.description,
+        home_page_url: &config.base_url,
+        feed_url: config.feed_url(Some("json")),
+        language: "en-GB",
+        authors: vec![JsonFeedAuthor { name: &config.author }],
+        items,
+    };
+
+    serde_json::to_string(&feed).unwrap()
+}
@@ -0,0 +1,802 @@
+use crate::audit::{list_audit, record_audit, summarise_changes, AuditFilter};
+use crate::auth::{
+    authenticate, issue_preview_token, list_preview_tokens, preview_post_id, revoke_preview_token,
+    verify_signature, Caller, Scope, SignatureError, SignedRequest,
+};
+use crate::feeds::{atom, escape_xml, json_feed, rss, FeedConfig};
+use crate::site::{layout, mount_path, post_page, robots_txt, site_sitemap};
+use crate::{
+    cgi_response, current_slug, delete_post, diff_revisions, get_post_by_slug, get_project_by_id,
+    get_revision, insert_post, list_posts, list_posts_full, list_projects, list_revisions,
+    list_tags, list_trash, publish_due_posts, query_param, read_stdin, restore_post,
+    restore_revision, search_posts, update_changes_publication, update_post, Cursor, NewPost,
+    PostFilter, PostStatus, RenderedPost, UpdatePost,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::{Connection, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
+
+// Every endpoint as a plain function from Request to Response, so the CGI
+// binaries, blog-serve and the FastCGI responder all run the same code.
+
+/// An API request, however it arrived
+#[derive(Debug, Default)]
+pub struct Request {
+    pub method: String,
+    /// Path as the client asked for it, mount point included
+    pub path: String,
+    /// Raw query string, without the '?'
+    pub query: String,
+    pub body: String,
+    /// Header values keyed by lowercase name, e.g. "x-cyril-key"
+    pub headers: HashMap<String, String>,
+    pub remote_addr: String,
+}
+
+impl Request {
+    /// Build a request from the CGI environment. Only methods that carry a
+    /// body read stdin.
+    pub fn from_cgi() -> Request {
+        let params: HashMap<String, String> = std::env::vars().collect();
+        let body = match params.get("REQUEST_METHOD").map(|m| m.as_str()) {
+            Some("POST" | "PATCH" | "PUT") => read_stdin(),
+            _ => String::new(),
+        };
+        Request::from_params(&params, body)
+    }
+
+    /// Build a request from CGI-style variables (REQUEST_METHOD, QUERY_STRING,
+    /// HTTP_*...), as found in the environment or FastCGI PARAMS
+    pub fn from_params(params: &HashMap<String, String>, body: String) -> Request {
+        let var = |name: &str| params.get(name).cloned().unwrap_or_default();
+
+        let headers = params
+            .iter()
+            .filter_map(|(name, value)| {
+                let header = name.strip_prefix("HTTP_")?;
+                Some((header.to_lowercase().replace('_', "-"), value.clone()))
+            })
+            .collect();
+
+        Request {
+            method: params.get("REQUEST_METHOD").cloned().unwrap_or_else(|| "GET".to_string()),
+            path: format!("{}{}", var("SCRIPT_NAME"), var("PATH_INFO")),
+            query: var("QUERY_STRING"),
+            body,
+            headers,
+            remote_addr: var("REMOTE_ADDR"),
+        }
+    }
+
+    pub fn param(&self, name: &str) -> Option<String> {
+        query_param(&self.query, name)
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(|v| v.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: String,
+    pub body: String,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &str, body: String) -> Response {
+        Response {
+            status,
+            content_type: content_type.to_string(),
+            body,
+        }
+    }
+
+    pub fn json<T: Serialize>(data: &T) -> Response {
+        Response::new(200, "application/json", serde_json::to_string(data).unwrap())
+    }
+
+    pub fn error(status: u16, message: &str) -> Response {
+        let body = serde_json::json!({"error": message}).to_string();
+        Response::new(status, "application/json", body)
+    }
+
+    pub fn xml_error(status: u16, message: &str) -> Response {
+        let body = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<error>{}</error>"#,
+            escape_xml(message)
+        );
+        Response::new(status, "application/xml", body)
+    }
+
+    pub fn html_error(status: u16, message: &str) -> Response {
+        let body = format!("<h2>{}</h2>\n", escape_xml(message));
+        let page = layout(&FeedConfig::from_env(), "", message, "", &body);
+        Response::new(status, HTML, page)
+    }
+
+    /// Write the response to stdout as CGI output
+    pub fn send_cgi(&self) {
+        cgi_response(self.status, &self.content_type, &self.body);
+    }
+}
+
+const HTML: &str = "text/html; charset=utf-8";
+const MAX_LIMIT: usize = 100;
+/// Preview links last at most 30 days
+const MAX_PREVIEW_HOURS: i64 = 30 * 24;
+
+fn unauthorised() -> Response {
+    Response::error(401, "Unauthorized")
+}
+
+fn forbidden(scope: Scope) -> Response {
+    Response::error(403, &format!("Key lacks the {} scope", scope.as_str()))
+}
+
+/// Work out who sent the request: by signature when it carries
+/// X-Cyril-Signature, otherwise by X-Cyril-Key. A bad signature is turned
+/// away outright; a bad or missing key just means nobody.
+fn authenticate_request(conn: &Connection, req: &Request) -> std::result::Result<Option<Caller>, Response> {
+    if let Some(signature) = req.header("x-cyril-signature") {
+        let signed = SignedRequest {
+            key_id: req.header("x-cyril-key-id").unwrap_or_default(),
+            timestamp: req.header("x-cyril-timestamp").unwrap_or_default(),
+            nonce: req.header("x-cyril-nonce").unwrap_or_default(),
+            signature,
+            method: &req.method,
+            path: &req.path,
+            query: &req.query,
+            body: &req.body,
+        };
+        return match verify_signature(conn, &signed) {
+            Ok(caller) => Ok(Some(caller)),
+            Err(e @ SignatureError::Database(_)) => Err(Response::error(500, &e.to_string())),
+            Err(e) => Err(Response::error(401, &e.to_string())),
+        };
+    }
+
+    // A database error counts as no key
+    Ok(authenticate(conn, req.header("x-cyril-key").unwrap_or_default()).unwrap_or(None))
+}
+
+/// Whoever sent the request, if they proved it
+fn caller(conn: &Connection, req: &Request) -> Option<Caller> {
+    authenticate_request(conn, req).unwrap_or(None)
+}
+
+/// The request's caller, provided their key has `scope`: otherwise 401 for
+/// a missing or dead key or a bad signature, 403 for a key without the scope
+pub fn require_auth(conn: &Connection, req: &Request, scope: Scope) -> std::result::Result<Caller, Response> {
+    match authenticate_request(conn, req)? {
+        Some(c) if c.can(scope) => Ok(c),
+        Some(_) => Err(forbidden(scope)),
+        None => Err(unauthorised()),
+    }
+}
+
+/// Log a change made by `caller`. The change itself has already happened, so
+/// a failure here is reported but doesn't fail the request.
+fn audit(conn: &Connection, req: &Request, caller: &Caller, action: &str, slug: &str, changes: serde_json::Value) {
+    if let Err(e) = record_audit(conn, &caller.label, &req.remote_addr, action, slug, &changes) {
+        eprintln!("Audit log write failed: {}", e);
+    }
+}
+
+/// Follow an old slug to the post's current one, so a change and its audit
+/// entry name the post as it's known now. Unknown slugs pass through for the
+/// caller to 404.
+fn resolve_slug(conn: &Connection, slug: String) -> String {
+    current_slug(conn, &slug).ok().flatten().unwrap_or(slug)
+}
+
+/// Accept a full RFC 3339 timestamp or a plain YYYY-MM-DD (midnight UTC)
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc())
+}
+
+/// Does the request ask for drafts? Both `drafts=true` and the Admin page's
+/// `include_drafts=true` count. Asking needs a key with the read-drafts scope.
+fn wants_drafts(conn: &Connection, req: &Request) -> std::result::Result<bool, Response> {
+    let asked = ["drafts", "include_drafts"]
+        .iter()
+        .any(|name| matches!(req.param(name).as_deref(), Some("true") | Some("1")));
+
+    if asked {
+        require_auth(conn, req, Scope::ReadDrafts)?;
+    }
+    Ok(asked)
+}
+
+/// GET /api/posts
+pub fn list(conn: &Connection, req: &Request) -> Response {
+    let include_drafts = match wants_drafts(conn, req) {
+        Ok(d) => d,
+        Err(response) => return response,
+    };
+
+    // Parse ?tag=xxx&project=xxx&repo=xxx&after=xxx&before=xxx
+    let mut filter = PostFilter {
+        include_drafts,
+        tag: req.param("tag"),
+        project: req.param("project"),
+        repo: req.param("repo"),
+        ..Default::default()
+    };
+
+    for (name, slot) in [
+        ("after", &mut filter.published_after),
+        ("before", &mut filter.published_before),
+    ] {
+        if let Some(value) = req.param(name) {
+            match parse_date(&value) {
+                Some(dt) => *slot = Some(dt),
+                None => return Response::error(400, &format!("Invalid {} parameter", name)),
+            }
+        }
+    }
+
+    // Parse ?limit=N&cursor=xxx
+    let limit = match req.param("limit").map(|l| l.parse::<usize>()) {
+        Some(Ok(n)) if n > 0 => Some(n.min(MAX_LIMIT)),
+        Some(_) => return Response::error(400, "Invalid limit parameter"),
+        None => None,
+    };
+
+    let cursor = match req.param("cursor").map(|c| Cursor::decode(&c)) {
+        Some(Some(c)) => Some(c),
+        Some(None) => return Response::error(400, "Invalid cursor parameter"),
+        None => None,
+    };
+
+    match list_posts(conn, &filter, limit, cursor.as_ref()) {
+        Ok(page) => Response::json(&page),
+        Err(e) => Response::error(500, &format!("Query failed: {}", e)),
+    }
+}
+
+/// GET /api/post?slug=xxx
+pub fn get(conn: &Connection, req: &Request) -> Response {
+    let slug = match req.param("slug") {
+        Some(s) => s,
+        None => return Response::error(400, "Missing slug parameter"),
+    };
+
+    // ?format=html adds content_html alongside the markdown
+    let as_html = req.param("format").as_deref() == Some("html");
+
+    // Drafts, scheduled and archived posts are kept for admins and holders of
+    // a preview link for that post; to anyone else they don't exist
+    let include_drafts = caller(conn, req).is_some_and(|c| c.can(Scope::ReadDrafts));
+    let preview = req
+        .param("preview")
+        .and_then(|token| preview_post_id(conn, &token).unwrap_or(None));
+
+    match get_post_by_slug(conn, &slug, include_drafts || preview.is_some()) {
+        Ok(Some(post)) if !include_drafts && !post.status.is_public() && post.id != preview => {
+            Response::error(404, "Post not found")
+        }
+        // Asked for an old slug: point the client at the current one
+        Ok(Some(post)) if post.slug != slug => {
+            let body = serde_json::json!({
+                "error": "Post moved",
+                "redirect": true,
+                "slug": post.slug,
+            });
+            Response::new(301, "application/json", body.to_string())
+        }
+        Ok(Some(post)) if as_html => Response::json(&RenderedPost::from(post)),
+        Ok(Some(post)) => Response::json(&post),
+        Ok(None) => Response::error(404, "Post not found"),
+        Err(e) => Response::error(500, &format!("Query failed: {}", e)),
+    }
+}
+
+/// POST /api/posts
+pub fn create(conn: &Connection, req: &Request) -> Response {
+    let caller = match require_auth(conn, req, Scope::Create) {
+        Ok(c) => c,
+        Err(response) => return response,
+    };
+
+    let post: NewPost = match serde_json::from_str(&req.body) {
+        Ok(p) => p,
+        Err(e) => return Response::error(400, &format!("Invalid JSON: {}", e)),
+    };
+
+    // Anything but a plain draft makes the post visible to someone
+    let publishes = post.publish == Some(true)
+        || post.publish_at.is_some()
+        || post.status.is_some_and(|s| s != PostStatus::Draft);
+    if publishes && !caller.can(Scope::Publish) {
+        return forbidden(Scope::Publish);
+    }
+
+    if post.title.trim().is_empty() {
+        return Response::error(400, "Title is required");
+    }
+
+    if post.content.trim().is_empty() {
+        return Response::error(400, "Content is required");
+    }
+
+    match insert_post(conn, &post) {
+        Ok((id, slug)) => {
+            audit(conn, req, &caller, "create", &slug, summarise_changes(&post));
+            Response::json(&serde_json::json!({
+                "success": true,
+                "id": id,
+                "slug": slug
+            }))
+        }
+        Err(e) => Response::error(500, &format!("Insert failed: {}", e)),
+    }
+}
+
+/// PATCH /api/post?slug=xxx
+pub fn update(conn: &Connection, req: &Request) -> Response {
+    let caller = match require_auth(conn, req, Scope::Update) {
+        Ok(c) => c,
+        Err(response) => return response,
+    };
+
+    let slug = match req.param("slug") {
+        Some(s) => resolve_slug(conn, s),
+        None => return Response::error(400, "Missing slug parameter"),
+    };
+
+    let update: UpdatePost = match serde_json::from_str(&req.body) {
+        Ok(u) => u,
+        Err(e) => return Response::error(400, &format!("Invalid JSON: {}", e)),
+    };
+
+    if !caller.can(Scope::Publish) {
+        match update_changes_publication(conn, &slug, &update) {
+            Ok(true) => return forbidden(Scope::Publish),
+            Ok(false) => {}
+            Err(e) => return Response::error(500, &format!("Update failed: {}", e)),
+        }
+    }
+
+    match update_post(conn, &slug, &update) {
+        Ok(true) => {
+            let mut changes = summarise_changes(&update);
+            // A rename may have landed on a different slug than asked for;
+            // log the one the post has now, and where it came from
+            let current = match update.slug {
+                Some(_) => get_post_by_slug(conn, &slug, true).ok().flatten().map(|p| p.slug),
+                None => None,
+            };
+            let current = current.unwrap_or_else(|| slug.clone());
+            if current != slug {
+                changes["renamed_from"] = serde_json::Value::String(slug.clone());
+            }
+            audit(conn, req, &caller, "update", &current, changes);
+            Response::json(&serde_json::json!({"success": true}))
+        }
+        Ok(false) => Response::error(404, "Post not found or no changes"),
+        Err(e) => Response::error(500, &format!("Update failed: {}", e)),
+    }
+}
+
+/// DELETE /api/post?slug=xxx
+pub fn delete(conn: &Connection, req: &Request) -> Response {
+    let caller = match require_auth(conn, req, Scope::Delete) {
+        Ok(c) => c,
+        Err(response) => return response,
+    };
+
+    let slug = match req.param("slug") {
+        Some(s) => resolve_slug(conn, s),
+        None => return Response::error(400, "Missing slug parameter"),
+    };
+
+    match delete_post(conn, &slug) {
+        Ok(true) => {
+            audit(conn, req, &caller, "delete", &slug, serde_json::json!({}));
+            Response::json(&serde_json::json!({"success": true}))
+        }
+        Ok(false) => Response::error(404, "Post not found"),
+        Err(e) => Response::error(500, &format!("Delete failed: {}", e)),
+    }
+}
+
+/// GET/POST/DELETE /api/post/preview?slug=xxx
+pub fn preview(conn: &Connection, req: &Request) -> Response {
+    // Anyone who can read drafts can share them
+    if let Err(response) = require_auth(conn, req, Scope::ReadDrafts) {
+        return response;
+    }
+
+    let slug = match req.param("slug") {
+        Some(s) => s,
+        None => return Response::error(400, "Missing slug parameter"),
+    };
+
+    let post = match get_post_by_slug(conn, &slug, true) {
+        Ok(Some(p)) => p,
+        Ok(None) => return Response::error(404, "Post not found"),
+        Err(e) => return Response::error(500, &format!("Query failed: {}", e)),
+    };
+    let post_id = post.id.unwrap_or_default();
+
+    match req.method.as_str() {
+        // POST ?slug=xxx[&hours=N] mints a link, good for 72 hours by default
+        "POST" => {
+            let hours = match req.param("hours").map(|h| h.parse::<i64>()) {
+                Some(Ok(h)) if h > 0 && h <= MAX_PREVIEW_HOURS => h,
+                Some(_) => return Response::error(400, "Invalid hours parameter"),
+                None => 72,
+            };
+
+            match issue_preview_token(conn, post_id, chrono::Duration::hours(hours)) {
+                Ok((record, token)) => Response::json(&serde_json::json!({
+                    "id": record.id,
+                    "token": token,
+                    "expires_at": record.expires_at,
+                    "url": format!("{}?preview={}", FeedConfig::from_env().post_url(&post), token),
+                })),
+                Err(e) => Response::error(500, &format!("Insert failed: {}", e)),
+            }
+        }
+        // DELETE ?slug=xxx&id=N revokes one
+        "DELETE" => {
+            let id = match req.param("id").map(|i| i.parse::<i64>()) {
+                Some(Ok(id)) => id,
+                Some(Err(_)) => return Response::error(400, "Invalid id parameter"),
+                None => return Response::error(400, "Missing id parameter"),
+            };
+
+            match revoke_preview_token(conn, post_id, id) {
+                Ok(true) => Response::json(&serde_json::json!({"success": true})),
+                Ok(false) => Response::error(404, "Preview link not found"),
+                Err(e) => Response::error(500, &format!("Revoke failed: {}", e)),
+            }
+        }
+        // GET lists them (never the tokens themselves)
+        _ => match list_preview_tokens(conn, post_id) {
+            Ok(tokens) => Response::json(&tokens),
+            Err(e) => Response::error(500, &format!("Query failed: {}", e)),
+        },
+    }
+}
+
+/// GET /api/search?q=xxx
+pub fn search(conn: &Connection, req: &Request) -> Response {
+    // Parse ?q=xxx&drafts=true
+    let q = match req.param("q") {
+        Some(q) if !q.trim().is_empty() => q,
+        _ => return Response::error(400, "Missing q parameter"),
+    };
+
+    let include_drafts = match wants_drafts(conn, req) {
+        Ok(d) => d,
+        Err(response) => return response,
+    };
+
+    match search_posts(conn, &q, include_drafts) {
+        Ok(results) => Response::json(&results),
+        Err(e) => Response::error(500, &format!("Search failed: {}", e)),
+    }
+}
+
+/// GET/POST /api/post/revisions?slug=xxx
+pub fn revisions(conn: &Connection, req: &Request) -> Response {
+    // Reading history can show unpublished text; restoring is an edit
+    let scope = if req.method == "POST" { Scope::Update } else { Scope::ReadDrafts };
+    let caller = match require_auth(conn, req, scope) {
+        Ok(c) => c,
+        Err(response) => return response,
+    };
+
+    let slug = match req.param("slug") {
+        Some(s) => resolve_slug(conn, s),
+        None => return Response::error(400, "Missing slug parameter"),
+    };
+
+    // Revision numbers are optional, but if given they must parse
+    let revision_param = |name: &str| -> std::result::Result<Option<i64>, String> {
+        match req.param(name) {
+            Some(v) => v
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("Invalid {} parameter", name)),
+            None => Ok(None),
+        }
+    };
+
+    let (revision, from, to) = match (
+        revision_param("revision"),
+        revision_param("from"),
+        revision_param("to"),
+    ) {
+        (Ok(r), Ok(f), Ok(t)) => (r, f, t),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => return Response::error(400, &e),
+    };
+
+    // POST ?slug=xxx&revision=N restores that revision
+    if req.method == "POST" {
+        let revision = match revision {
+            Some(r) => r,
+            None => return Response::error(400, "Missing revision parameter"),
+        };
+
+        return match restore_revision(conn, &slug, revision) {
+            Ok(true) => {
+                let changes = serde_json::json!({"revision": revision});
+                audit(conn, req, &caller, "restore-revision", &slug, changes);
+                Response::json(&serde_json::json!({"success": true}))
+            }
+            Ok(false) => Response::error(404, "Revision not found"),
+            Err(e) => Response::error(500, &format!("Restore failed: {}", e)),
+        };
+    }
+
+    // GET ?slug=xxx&from=N[&to=M] diffs two revisions (to defaults to latest)
+    if let Some(from) = from {
+        let old = get_revision(conn, &slug, Some(from));
+        let new = get_revision(conn, &slug, to);
+
+        return match (old, new) {
+            (Ok(Some(old)), Ok(Some(new))) => Response::json(&diff_revisions(&old, &new)),
+            (Ok(_), Ok(_)) => Response::error(404, "Revision not found"),
+            (Err(e), _) | (_, Err(e)) => Response::error(500, &format!("Query failed: {}", e)),
+        };
+    }
+
+    // GET ?slug=xxx&revision=N fetches one revision
+    if revision.is_some() {
+        return match get_revision(conn, &slug, revision) {
+            Ok(Some(r)) => Response::json(&r),
+            Ok(None) => Response::error(404, "Revision not found"),
+            Err(e) => Response::error(500, &format!("Query failed: {}", e)),
+        };
+    }
+
+    // GET ?slug=xxx lists them
+    match list_revisions(conn, &slug) {
+        Ok(revisions) if revisions.is_empty() => Response::error(404, "Post not found"),
+        Ok(revisions) => Response::json(&revisions),
+        Err(e) => Response::error(500, &format!("Query failed: {}", e)),
+    }
+}
+
+/// GET/POST /api/trash
+pub fn trash(conn: &Connection, req: &Request) -> Response {
+    let caller = match require_auth(conn, req, Scope::Delete) {
+        Ok(c) => c,
+        Err(response) => return response,
+    };
+
+    // POST ?slug=xxx restores a trashed post
+    if req.method == "POST" {
+        let slug = match req.param("slug") {
+            Some(s) => s,
+            None => return Response::error(400, "Missing slug parameter"),
+        };
+
+        return match restore_post(conn, &slug) {
+            Ok(true) => {
+                audit(conn, req, &caller, "restore", &slug, serde_json::json!({}));
+                Response::json(&serde_json::json!({"success": true}))
+            }
+            Ok(false) => Response::error(404, "Post not in trash"),
+            Err(e) => Response::error(500, &format!("Restore failed: {}", e)),
+        };
+    }
+
+    match list_trash(conn) {
+        Ok(posts) => Response::json(&posts),
+        Err(e) => Response::error(500, &format!("Query failed: {}", e)),
+    }
+}
+
+/// GET /api/audit
+pub fn audit_log(conn: &Connection, req: &Request) -> Response {
+    if let Err(response) = require_auth(conn, req, Scope::ReadAudit) {
+        return response;
+    }
+
+    // Parse ?slug=xxx&after=xxx&before=xxx&limit=N
+    let mut filter = AuditFilter {
+        slug: req.param("slug"),
+        ..Default::default()
+    };
+
+    for (name, slot) in [("after", &mut filter.after), ("before", &mut filter.before)] {
+        if let Some(value) = req.param(name) {
+            match parse_date(&value) {
+                Some(dt) => *slot = Some(dt),
+                None => return Response::error(400, &format!("Invalid {} parameter", name)),
+            }
+        }
+    }
+
+    let limit = match req.param("limit").map(|l| l.parse::<usize>()) {
+        Some(Ok(n)) if n > 0 => n.min(MAX_LIMIT),
+        Some(_) => return Response::error(400, "Invalid limit parameter"),
+        None => MAX_LIMIT,
+    };
+
+    match list_audit(conn, &filter, limit) {
+        Ok(entries) => Response::json(&entries),
+        Err(e) => Response::error(500, &format!("Query failed: {}", e)),
+    }
+}
+
+/// GET /api/tags
+pub fn tags(conn: &Connection, _req: &Request) -> Response {
+    match list_tags(conn) {
+        Ok(tags) => Response::json(&tags),
+        Err(e) => Response::error(500, &format!("Query failed: {}", e)),
+    }
+}
+
+/// GET /api/projects
+pub fn projects(conn: &Connection, _req: &Request) -> Response {
+    match list_projects(conn) {
+        Ok(projects) => Response::json(&projects),
+        Err(e) => Response::error(500, &format!("Query failed: {}", e)),
+    }
+}
+
+/// GET /feed.xml
+pub fn feed(conn: &Connection, req: &Request) -> Response {
+    let mut config = FeedConfig::from_env();
+
+    // ?format=atom for Atom 1.0, ?format=json for JSON Feed 1.1; RSS 2.0 otherwise
+    let format = req.param("format").unwrap_or_default();
+
+    // ?tag= and ?project= (a project id) narrow the feed; both may be given
+    let mut filter = PostFilter::default();
+
+    if let Some(tag) = req.param("tag").filter(|t| !t.is_empty()) {
+        config.for_tag(&tag);
+        filter.tag = Some(tag);
+    }
+
+    if let Some(id) = req.param("project").filter(|p| !p.is_empty()) {
+        match get_project_by_id(conn, &id) {
+            Ok(Some(project)) => config.for_project(&project),
+            Ok(None) => return Response::xml_error(404, "Project not found"),
+            Err(e) => return Response::xml_error(500, &format!("Query failed: {}", e)),
+        }
+        filter.project = Some(id);
+    }
+
+    // Get the 20 most recent published posts
+    let posts = match list_posts_full(conn, &filter, Some(20)) {
+        Ok(p) => p,
+        Err(e) => return Response::xml_error(500, &format!("Query failed: {}", e)),
+    };
+
+    match format.as_str() {
+        "atom" => Response::new(200, "application/atom+xml; charset=utf-8", atom(&config, &posts)),
+        "json" => Response::new(200, "application/feed+json; charset=utf-8", json_feed(&config, &posts)),
+        _ => Response::new(200, "application/rss+xml; charset=utf-8", rss(&config, &posts)),
+    }
+}
+
+/// GET /sitemap.xml
+pub fn sitemap(conn: &Connection, _req: &Request) -> Response {
+    match site_sitemap(conn, &FeedConfig::from_env()) {
+        Ok(xml) => Response::new(200, "application/xml; charset=utf-8", xml),
+        Err(e) => Response::xml_error(500, &format!("Query failed: {}", e)),
+    }
+}
+
+/// GET /robots.txt
+pub fn robots(_conn: &Connection, _req: &Request) -> Response {
+    Response::new(200, "text/plain; charset=utf-8", robots_txt(&FeedConfig::from_env()))
+}
+
+/// GET /post.html?slug=xxx
+pub fn page(conn: &Connection, req: &Request) -> Response {
+    let slug = match req.param("slug") {
+        Some(s) if !s.is_empty() => s,
+        _ => return Response::html_error(400, "Missing slug parameter"),
+    };
+
+    // Old slugs resolve to the post, whose canonical URL names the current one.
+    // Only posts a reader could open anyway get a page.
+    match get_post_by_slug(conn, &slug, false) {
+        Ok(Some(post)) => Response::new(200, HTML, post_page(&FeedConfig::from_env(), &post)),
+        Ok(None) => Response::html_error(404, "Post not found"),
+        Err(e) => Response::html_error(500, &format!("Query failed: {}", e)),
+    }
+}
+
+type Handler = fn(&Connection, &Request) -> Response;
+
+/// Every endpoint by path (relative to the blog's mount point) and method
+const ROUTES: &[(&str, &str, Handler)] = &[
+    ("/api/posts", "GET", list),
+    ("/api/posts", "POST", create),
+    ("/api/post", "GET", get),
+    ("/api/post", "PATCH", update),
+    ("/api/post", "DELETE", delete),
+    ("/api/post/revisions", "GET", revisions),
+    ("/api/post/revisions", "POST", revisions),
+    ("/api/post/preview", "GET", preview),
+    ("/api/post/preview", "POST", preview),
+    ("/api/post/preview", "DELETE", preview),
+    ("/api/search", "GET", search),
+    ("/api/trash", "GET", trash),
+    ("/api/trash", "POST", trash),
+    ("/api/audit", "GET", audit_log),
+    ("/api/tags", "GET", tags),
+    ("/api/projects", "GET", projects),
+    ("/feed.xml", "GET", feed),
+    ("/sitemap.xml", "GET", sitemap),
+    ("/robots.txt", "GET", robots),
+    ("/post.html", "GET", page),
+];
+
+/// Dispatch a request for `path` to its handler, for the long-running
+/// servers. `path` may include the blog's mount point ("/cyril/api/posts").
+/// Scheduled posts are caught up first, as open_db does for CGI.
+pub fn route(conn: &Connection, path: &str, req: &Request) -> Response {
+    let mount = mount_path(&FeedConfig::from_env().base_url).to_string();
+    let path = match path.strip_prefix(mount.as_str()) {
+        Some(rest) if !mount.is_empty() && rest.starts_with('/') => rest,
+        _ => path,
+    };
+
+    let mut known_path = false;
+
+    for (route_path, method, handler) in ROUTES {
+        if *route_path != path {
+            continue;
+        }
+        known_path = true;
+        if *method == req.method {
+            if let Err(e) = publish_due_posts(conn) {
+                return Response::error(500, &format!("Database error: {}", e));
+            }
+            return handler(conn, req);
+        }
+    }
+
+    if known_path {
+        Response::error(405, "Method not allowed")
+    } else {
+        Response::error(404, "Not found")
+    }
+}
+
+/// Start `workers` threads, each with its own database connection held for
+/// the life of the process, and return a channel that hands them work
+pub fn spawn_workers<T, F>(workers: usize, serve: F) -> Result<mpsc::Sender<T>>
+where
+    T: Send + 'static,
+    F: Fn(&Connection, T) + Send + Clone + 'static,
+{
+    let (sender, receiver) = mpsc::channel::<T>();
+    let receiver = Arc::new(Mutex::new(receiver));
+
+    for _ in 0..workers {
+        let conn = crate::open_db()?;
+        let receiver = Arc::clone(&receiver);
+        let serve = serve.clone();
+
+        std::thread::spawn(move || loop {
+            let job = match receiver.lock().unwrap().recv() {
+                Ok(job) => job,
+                Err(_) => return,
+            };
+            serve(&conn, job);
+        });
+    }
+
+    Ok(sender)
+}
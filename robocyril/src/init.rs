@@ -1,9 +1,11 @@
-use robocyril_api::{open_db, init_db, db_path};
+use robocyril_api::{init_db, db_path, migration_status};
+use rusqlite::Connection;
 use std::fs;
 use std::path::Path;
 
 fn main() {
     let path = db_path();
+    let status_only = std::env::args().skip(1).any(|arg| arg == "--status");
 
     // Ensure directory exists
     let db_dir = Path::new(&path).parent().unwrap();
@@ -12,17 +14,59 @@ fn main() {
         std::process::exit(1);
     }
 
-    match open_db() {
-        Ok(conn) => {
-            if let Err(e) = init_db(&conn) {
-                eprintln!("Failed to initialise database: {}", e);
-                std::process::exit(1);
+    // Open directly rather than via open_db, which would migrate behind our back
+    let conn = match Connection::open(&path) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("Failed to open database: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if status_only {
+        print_status(&conn, &path);
+        return;
+    }
+
+    match init_db(&conn) {
+        Ok(applied) if applied.is_empty() => {
+            println!("Database at {} is up to date", path);
+        }
+        Ok(applied) => {
+            for version in &applied {
+                println!("Applied migration {}", version);
             }
             println!("Database initialised at {}", path);
         }
         Err(e) => {
-            eprintln!("Failed to open database: {}", e);
+            eprintln!("Failed to initialise database: {}", e);
             std::process::exit(1);
         }
     }
 }
+
+fn print_status(conn: &Connection, path: &str) {
+    let status = match migration_status(conn) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Failed to read migration status: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("Migrations for {}", path);
+
+    let mut pending = 0;
+    for migration in &status {
+        let state = match migration.applied_at {
+            Some(at) => format!("applied {}", at.format("%Y-%m-%d %H:%M:%S")),
+            None => {
+                pending += 1;
+                "pending".to_string()
+            }
+        };
+        println!("  {:>3}  {:<40} {}", migration.version, migration.name, state);
+    }
+
+    println!("{} applied, {} pending", status.len() - pending, pending);
+}
@@ -1,11 +1,124 @@
 use chrono::{DateTime, Utc};
-use rusqlite::{Connection, Result};
+use rusqlite::{Connection, OptionalExtension, Result};
 use serde::{Deserialize, Serialize};
 
+pub mod audit;
+pub mod auth;
+pub mod feeds;
+pub mod handlers;
+pub mod migrations;
+pub mod render;
+pub mod site;
+
+pub use migrations::{migrate, migration_status, MigrationStatus};
+
 pub fn db_path() -> String {
     std::env::var("BLOG_DB_PATH").unwrap_or_else(|_| "/var/lib/robocyril/blog.db".to_string())
 }
 
+/// Where a post is in its life. Only published posts appear in listings and
+/// feeds; unlisted ones are reachable by slug alone; archived ones are kept for
+/// admins but hidden everywhere public.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PostStatus {
+    Draft,
+    /// Published, but published_at is still in the future
+    Scheduled,
+    Published,
+    Unlisted,
+    Archived,
+}
+
+impl PostStatus {
+    /// The value kept in posts.status. Scheduled is stored as published.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PostStatus::Draft => "draft",
+            PostStatus::Scheduled | PostStatus::Published => "published",
+            PostStatus::Unlisted => "unlisted",
+            PostStatus::Archived => "archived",
+        }
+    }
+
+    fn from_db(stored: &str, published_at: Option<DateTime<Utc>>) -> PostStatus {
+        match stored {
+            "published" if published_at.is_some_and(|at| at > Utc::now()) => PostStatus::Scheduled,
+            "published" => PostStatus::Published,
+            "unlisted" => PostStatus::Unlisted,
+            "archived" => PostStatus::Archived,
+            _ => PostStatus::Draft,
+        }
+    }
+
+    /// Can a post in this state be read by slug without credentials?
+    pub fn is_public(&self) -> bool {
+        matches!(self, PostStatus::Published | PostStatus::Unlisted)
+    }
+}
+
+/// A post's status and published_at, the two things publishing changes
+type Publication = (PostStatus, Option<DateTime<Utc>>);
+
+/// Work out a post's stored status and published_at after a create or update.
+/// An explicit status wins over publish/publish_at; publish: true on a post
+/// that's already live keeps its original date, and leaves an archived post
+/// archived (only `status` brings one back).
+fn next_publication(
+    current: Publication,
+    publish: Option<bool>,
+    publish_at: Option<DateTime<Utc>>,
+    status: Option<PostStatus>,
+) -> Publication {
+    let (mut stored, mut published_at) = current;
+    let now = Utc::now();
+
+    if stored == PostStatus::Scheduled {
+        stored = PostStatus::Published;
+    }
+
+    match publish {
+        Some(true) if stored == PostStatus::Archived => {}
+        Some(true) => {
+            if stored == PostStatus::Draft {
+                stored = PostStatus::Published;
+            }
+            published_at.get_or_insert(now);
+        }
+        Some(false) => {
+            stored = PostStatus::Draft;
+            published_at = None;
+        }
+        None => {}
+    }
+
+    if let Some(at) = publish_at {
+        if matches!(stored, PostStatus::Draft | PostStatus::Archived) {
+            stored = PostStatus::Published;
+        }
+        published_at = Some(at);
+    }
+
+    match status {
+        Some(PostStatus::Draft) => {
+            stored = PostStatus::Draft;
+            published_at = None;
+        }
+        Some(PostStatus::Published) | Some(PostStatus::Scheduled) => {
+            stored = PostStatus::Published;
+            published_at.get_or_insert(now);
+        }
+        Some(PostStatus::Unlisted) => {
+            stored = PostStatus::Unlisted;
+            published_at.get_or_insert(now);
+        }
+        Some(PostStatus::Archived) => stored = PostStatus::Archived,
+        None => {}
+    }
+
+    (stored, published_at)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Post {
     pub id: Option<i64>,
@@ -17,6 +130,35 @@ pub struct Post {
     pub published_at: Option<DateTime<Utc>>,
     pub tags: Vec<String>,
     pub commit_range: Option<String>,
+    pub status: PostStatus,
+    /// Last time anything about the post changed
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Post {
+    /// When the post last changed for readers. A post published after its
+    /// last edit was still "updated" when it went out.
+    pub fn last_modified(&self) -> DateTime<Utc> {
+        match self.published_at {
+            Some(published) if published > self.updated_at => published,
+            _ => self.updated_at,
+        }
+    }
+}
+
+/// A post with its content also rendered to HTML (`?format=html`)
+#[derive(Debug, Serialize)]
+pub struct RenderedPost {
+    #[serde(flatten)]
+    pub post: Post,
+    pub content_html: String,
+}
+
+impl From<Post> for RenderedPost {
+    fn from(post: Post) -> RenderedPost {
+        let content_html = render::markdown_to_html(&post.content);
+        RenderedPost { post, content_html }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -27,6 +169,12 @@ pub struct NewPost {
     pub tags: Option<Vec<String>>,
     pub commit_range: Option<String>,
     pub publish: Option<bool>,
+    /// Go live at this time instead of now. Implies publish.
+    pub publish_at: Option<DateTime<Utc>>,
+    /// Explicit slug; derived from the title when absent
+    pub slug: Option<String>,
+    /// Create straight into a given state, e.g. unlisted
+    pub status: Option<PostStatus>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -37,6 +185,7 @@ pub struct PostSummary {
     pub created_at: DateTime<Utc>,
     pub published_at: Option<DateTime<Utc>>,
     pub tags: Vec<String>,
+    pub status: PostStatus,
 }
 
 // Project structs for project tagging
@@ -59,54 +208,113 @@ pub struct NewProject {
     pub short_description: String,
 }
 
+/// Open the blog database, bring its schema up to date and catch up on any
+/// scheduled posts that have gone live. Every binary goes through here, so a
+/// freshly deployed build migrates the database on its first request.
 pub fn open_db() -> Result<Connection> {
-    Connection::open(db_path())
-}
-
-pub fn init_db(conn: &Connection) -> Result<()> {
-    conn.execute_batch(
-        "
-        CREATE TABLE IF NOT EXISTS posts (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            slug TEXT UNIQUE NOT NULL,
-            title TEXT NOT NULL,
-            content TEXT NOT NULL,
-            repo TEXT,
-            created_at TEXT NOT NULL,
-            published_at TEXT,
-            tags TEXT NOT NULL DEFAULT '[]',
-            commit_range TEXT
-        );
-        CREATE INDEX IF NOT EXISTS idx_posts_published ON posts(published_at);
-        CREATE INDEX IF NOT EXISTS idx_posts_created ON posts(created_at);
-
-        CREATE TABLE IF NOT EXISTS projects (
-            id TEXT PRIMARY KEY,
-            name TEXT NOT NULL,
-            repo TEXT NOT NULL,
-            description TEXT NOT NULL,
-            short_description TEXT NOT NULL,
-            created_at TEXT NOT NULL
-        );
-        CREATE INDEX IF NOT EXISTS idx_projects_created ON projects(created_at);
-        ",
-    )
+    let conn = Connection::open(db_path())?;
+    migrate(&conn)?;
+    publish_due_posts(&conn)?;
+    Ok(conn)
 }
 
-pub fn insert_post(conn: &Connection, post: &NewPost) -> Result<i64> {
-    let slug = slug::slugify(&post.title);
+pub fn init_db(conn: &Connection) -> Result<Vec<i64>> {
+    migrate(conn)
+}
+
+// Column lists shared by every query that builds a Post or PostSummary, so the
+// row readers below can rely on the order.
+const POST_COLUMNS: &str =
+    "posts.id, posts.slug, posts.title, posts.content, posts.repo, posts.created_at, posts.published_at, posts.tags, posts.commit_range, posts.status, posts.updated_at";
+const SUMMARY_COLUMNS: &str =
+    "posts.id, posts.slug, posts.title, posts.created_at, posts.published_at, posts.tags, posts.status";
+
+fn parse_time(s: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(s)
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+fn post_from_row(row: &rusqlite::Row) -> Result<Post> {
+    let tags_str: String = row.get(7)?;
+    let created_str: String = row.get(5)?;
+    let published_at = row.get::<_, Option<String>>(6)?.as_deref().map(parse_time);
+    let status_str: String = row.get(9)?;
+    let updated_str: Option<String> = row.get(10)?;
+
+    Ok(Post {
+        id: Some(row.get(0)?),
+        slug: row.get(1)?,
+        title: row.get(2)?,
+        content: row.get(3)?,
+        repo: row.get(4)?,
+        created_at: parse_time(&created_str),
+        published_at,
+        tags: serde_json::from_str(&tags_str).unwrap_or_default(),
+        commit_range: row.get(8)?,
+        status: PostStatus::from_db(&status_str, published_at),
+        updated_at: parse_time(updated_str.as_deref().unwrap_or(&created_str)),
+    })
+}
+
+fn summary_from_row(row: &rusqlite::Row) -> Result<PostSummary> {
+    let tags_str: String = row.get(5)?;
+    let created_str: String = row.get(3)?;
+    let published_at = row.get::<_, Option<String>>(4)?.as_deref().map(parse_time);
+    let status_str: String = row.get(6)?;
+
+    Ok(PostSummary {
+        id: row.get(0)?,
+        slug: row.get(1)?,
+        title: row.get(2)?,
+        created_at: parse_time(&created_str),
+        published_at,
+        tags: serde_json::from_str(&tags_str).unwrap_or_default(),
+        status: PostStatus::from_db(&status_str, published_at),
+    })
+}
+
+/// Pick a free slug for a post: slugify the wanted text and, if another post
+/// (or another post's old slug) already has it, append -2, -3, ... until it
+/// doesn't. `post_id` is the post being (re)slugged, whose own slugs don't count.
+pub fn unique_slug(conn: &Connection, wanted: &str, post_id: Option<i64>) -> Result<String> {
+    let mut base = slug::slugify(wanted);
+    if base.is_empty() {
+        base = "post".to_string();
+    }
+
+    let mut candidate = base.clone();
+    let mut n = 1;
+
+    loop {
+        let taken: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM posts WHERE slug = ?1 AND id IS NOT ?2)
+                 OR EXISTS (SELECT 1 FROM slug_aliases WHERE slug = ?1 AND post_id IS NOT ?2)",
+            (&candidate, post_id),
+            |row| row.get(0),
+        )?;
+
+        if !taken {
+            return Ok(candidate);
+        }
+
+        n += 1;
+        candidate = format!("{}-{}", base, n);
+    }
+}
+
+/// Create a post, returning its id and the slug it was actually given
+pub fn insert_post(conn: &Connection, post: &NewPost) -> Result<(i64, String)> {
+    let wanted = post.slug.as_deref().unwrap_or(&post.title);
+    let slug = unique_slug(conn, wanted, None)?;
     let now = Utc::now();
-    let is_published = post.publish.unwrap_or(false);
-    let published_at = if is_published {
-        Some(now)
-    } else {
-        None
-    };
+    let (status, published_at) =
+        next_publication((PostStatus::Draft, None), post.publish, post.publish_at, post.status);
     let tags_json = serde_json::to_string(&post.tags.clone().unwrap_or_default()).unwrap();
 
     conn.execute(
-        "INSERT INTO posts (slug, title, content, repo, created_at, published_at, tags, commit_range)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        "INSERT INTO posts (slug, title, content, repo, created_at, published_at, tags, commit_range, status, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?5)",
         (
             &slug,
             &post.title,
@@ -116,66 +324,124 @@ pub fn insert_post(conn: &Connection, post: &NewPost) -> Result<i64> {
             published_at.map(|t| t.to_rfc3339()),
             &tags_json,
             &post.commit_range,
+            status.as_str(),
         ),
     )?;
 
-    // Sync project if post is published and has a project tag
-    if is_published {
-        let _ = sync_project_from_post(conn, post);
+    let id = conn.last_insert_rowid();
+    set_post_tags(conn, id, post.tags.as_deref().unwrap_or(&[]))?;
+    record_revision(conn, &slug)?;
+
+    // Sync project if post is live now and has a project tag
+    if published_at.is_some() {
+        publish_due_posts(conn)?;
     }
 
-    Ok(conn.last_insert_rowid())
+    Ok((id, slug))
 }
 
-pub fn get_post_by_slug(conn: &Connection, slug: &str) -> Result<Option<Post>> {
-    let mut stmt = conn.prepare(
-        "SELECT id, slug, title, content, repo, created_at, published_at, tags, commit_range
-         FROM posts WHERE slug = ?1",
-    )?;
+/// Fetch a post by its current slug or any slug it has had before. Callers can
+/// compare the returned post's slug with the one asked for to spot a rename.
+/// Unless `include_drafts` is set, only posts a reader may open (published or
+/// unlisted) are found.
+pub fn get_post_by_slug(conn: &Connection, slug: &str, include_drafts: bool) -> Result<Option<Post>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}
+         FROM posts
+         WHERE id = (SELECT id FROM posts WHERE slug = ?1
+                     UNION ALL
+                     SELECT post_id FROM slug_aliases WHERE slug = ?1
+                     LIMIT 1)
+           AND deleted_at IS NULL",
+        POST_COLUMNS
+    ))?;
 
     let mut rows = stmt.query([slug])?;
 
-    if let Some(row) = rows.next()? {
-        let tags_str: String = row.get(7)?;
-        let tags: Vec<String> = serde_json::from_str(&tags_str).unwrap_or_default();
-        let created_str: String = row.get(5)?;
-        let published_str: Option<String> = row.get(6)?;
-
-        Ok(Some(Post {
-            id: Some(row.get(0)?),
-            slug: row.get(1)?,
-            title: row.get(2)?,
-            content: row.get(3)?,
-            repo: row.get(4)?,
-            created_at: DateTime::parse_from_rfc3339(&created_str)
-                .unwrap()
-                .with_timezone(&Utc),
-            published_at: published_str.map(|s| {
-                DateTime::parse_from_rfc3339(&s)
-                    .unwrap()
-                    .with_timezone(&Utc)
-            }),
-            tags,
-            commit_range: row.get(8)?,
-        }))
-    } else {
-        Ok(None)
+    match rows.next()? {
+        Some(row) => {
+            let post = post_from_row(row)?;
+            Ok(Some(post).filter(|p| include_drafts || p.status.is_public()))
+        }
+        None => Ok(None),
     }
 }
 
+/// The slug a live post goes by now, following an old slug to it. Updates,
+/// deletes and restores go through this so they find the same post GET does.
+pub fn current_slug(conn: &Connection, slug: &str) -> Result<Option<String>> {
+    Ok(get_post_by_slug(conn, slug, true)?.map(|post| post.slug))
+}
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct UpdatePost {
     pub title: Option<String>,
     pub content: Option<String>,
     pub tags: Option<Vec<String>>,
     pub publish: Option<bool>,
+    /// Schedule (or reschedule) the post to go live at this time
+    pub publish_at: Option<DateTime<Utc>>,
+    /// Move the post to another state; `publish: false` is shorthand for draft
+    pub status: Option<PostStatus>,
+    /// Rename the post. The old slug is kept as an alias so links keep working.
+    pub slug: Option<String>,
+}
+
+/// A live post's id, status and published_at, as next_publication takes them
+fn publication_state(conn: &Connection, slug: &str) -> Result<Option<(i64, Publication)>> {
+    conn.query_row(
+        "SELECT id, status, published_at FROM posts WHERE slug = ?1 AND deleted_at IS NULL",
+        [slug],
+        |row| {
+            let status: String = row.get(1)?;
+            let published_at = row.get::<_, Option<String>>(2)?.as_deref().map(parse_time);
+            Ok((row.get::<_, i64>(0)?, (PostStatus::from_db(&status, published_at), published_at)))
+        },
+    )
+    .optional()
+}
+
+/// Would update_post change the post's stored status or published_at? A
+/// request that only repeats the current state (publish: true on a live post)
+/// doesn't count.
+pub fn update_changes_publication(conn: &Connection, slug: &str, update: &UpdatePost) -> Result<bool> {
+    let slug = match current_slug(conn, slug)? {
+        Some(s) => s,
+        None => return Ok(false),
+    };
+    let (_, (status, published_at)) = match publication_state(conn, &slug)? {
+        Some(s) => s,
+        None => return Ok(false),
+    };
+
+    let next = next_publication((status, published_at), update.publish, update.publish_at, update.status);
+    Ok(next.0.as_str() != status.as_str() || next.1 != published_at)
 }
 
 pub fn update_post(conn: &Connection, slug: &str, update: &UpdatePost) -> Result<bool> {
+    let slug = match current_slug(conn, slug)? {
+        Some(s) => s,
+        None => return Ok(false),
+    };
+    let slug = slug.as_str();
+    let (post_id, (current_status, current_published_at)) = match publication_state(conn, slug)? {
+        Some(c) => c,
+        None => return Ok(false),
+    };
+
     // Build dynamic update query
     let mut sets = Vec::new();
     let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
 
+    let mut new_slug = slug.to_string();
+    if let Some(wanted) = &update.slug {
+        new_slug = unique_slug(conn, wanted, Some(post_id))?;
+        if new_slug != slug {
+            sets.push("slug = ?");
+            params.push(Box::new(new_slug.clone()));
+        }
+    }
+
     if let Some(title) = &update.title {
         sets.push("title = ?");
         params.push(Box::new(title.clone()));
@@ -189,123 +455,656 @@ pub fn update_post(conn: &Connection, slug: &str, update: &UpdatePost) -> Result
         let tags_json = serde_json::to_string(tags).unwrap_or_default();
         params.push(Box::new(tags_json));
     }
-    let is_publishing = update.publish == Some(true);
-    if is_publishing {
+
+    let (status, published_at) = next_publication(
+        (current_status, current_published_at),
+        update.publish,
+        update.publish_at,
+        update.status,
+    );
+    let status_changed = status.as_str() != current_status.as_str();
+    let date_changed = published_at != current_published_at;
+    if status_changed {
+        sets.push("status = ?");
+        params.push(Box::new(status.as_str()));
+    }
+    if date_changed {
         sets.push("published_at = ?");
-        params.push(Box::new(Utc::now().to_rfc3339()));
+        params.push(Box::new(published_at.map(|t| t.to_rfc3339())));
+    }
+    let text_changed = update.title.is_some() || update.content.is_some() || update.tags.is_some();
+    if date_changed || text_changed {
+        // Clearing the sync marker makes publish_due_posts sync the project
+        // again once the post is live, so edits to a live post carry over
+        sets.push("project_synced_at = NULL");
     }
 
     if sets.is_empty() {
         return Ok(false);
     }
 
-    let sql = format!("UPDATE posts SET {} WHERE slug = ?", sets.join(", "));
-    params.push(Box::new(slug.to_string()));
+    sets.push("updated_at = ?");
+    params.push(Box::new(Utc::now().to_rfc3339()));
+
+    let sql = format!("UPDATE posts SET {} WHERE id = ?", sets.join(", "));
+    params.push(Box::new(post_id));
 
     let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
     let rows = conn.execute(&sql, param_refs.as_slice())?;
 
-    // Sync project when publishing
-    if is_publishing && rows > 0 {
-        if let Ok(Some(post)) = get_post_by_slug(conn, slug) {
-            let new_post = NewPost {
-                title: post.title,
-                content: post.content,
-                repo: post.repo,
-                tags: Some(post.tags),
-                commit_range: post.commit_range,
-                publish: Some(true),
-            };
-            let _ = sync_project_from_post(conn, &new_post);
-        }
+    if new_slug != slug {
+        // Moving back to an old slug retires that alias
+        conn.execute("DELETE FROM slug_aliases WHERE slug = ?1", [&new_slug])?;
+        conn.execute(
+            "INSERT INTO slug_aliases (slug, post_id, created_at) VALUES (?1, ?2, ?3)",
+            (slug, post_id, Utc::now().to_rfc3339()),
+        )?;
+    }
+
+    if let Some(tags) = &update.tags {
+        set_post_tags(conn, post_id, tags)?;
+    }
+
+    if text_changed && rows > 0 {
+        record_revision(conn, &new_slug)?;
+    }
+
+    if (status_changed || date_changed || text_changed) && rows > 0 {
+        publish_due_posts(conn)?;
     }
 
     Ok(rows > 0)
 }
 
-pub fn delete_post(conn: &Connection, slug: &str) -> Result<bool> {
-    let rows = conn.execute("DELETE FROM posts WHERE slug = ?", [slug])?;
-    Ok(rows > 0)
+// Revision history
+
+#[derive(Debug, Serialize)]
+pub struct RevisionSummary {
+    pub revision: i64,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
 }
 
-pub fn list_posts(conn: &Connection, include_drafts: bool) -> Result<Vec<PostSummary>> {
-    let sql = if include_drafts {
-        "SELECT id, slug, title, created_at, published_at, tags FROM posts ORDER BY created_at DESC"
-    } else {
-        "SELECT id, slug, title, created_at, published_at, tags FROM posts WHERE published_at IS NOT NULL ORDER BY published_at DESC"
-    };
+#[derive(Debug, Serialize)]
+pub struct Revision {
+    pub revision: i64,
+    pub title: String,
+    pub content: String,
+    pub tags: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
 
-    let mut stmt = conn.prepare(sql)?;
-    let mut rows = stmt.query([])?;
-    let mut posts = Vec::new();
+#[derive(Debug, Serialize)]
+pub struct DiffLine {
+    /// "equal", "insert" or "delete"
+    pub op: &'static str,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevisionDiff {
+    pub from: RevisionSummary,
+    pub to: RevisionSummary,
+    pub tags_added: Vec<String>,
+    pub tags_removed: Vec<String>,
+    pub lines: Vec<DiffLine>,
+}
+
+/// Snapshot the post's current title, content and tags as its next revision.
+/// Skipped when nothing differs from the latest revision, so a PATCH that
+/// resends the same title doesn't pad out the history.
+fn record_revision(conn: &Connection, slug: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO post_revisions (post_id, revision, title, content, tags, created_at)
+         SELECT p.id,
+                COALESCE((SELECT MAX(revision) FROM post_revisions WHERE post_id = p.id), 0) + 1,
+                p.title, p.content, p.tags, ?2
+         FROM posts p
+         WHERE p.slug = ?1
+           AND NOT EXISTS (
+               SELECT 1 FROM post_revisions r
+               WHERE r.post_id = p.id
+                 AND r.revision = (SELECT MAX(revision) FROM post_revisions WHERE post_id = p.id)
+                 AND r.title = p.title AND r.content = p.content AND r.tags = p.tags
+           )",
+        (slug, Utc::now().to_rfc3339()),
+    )?;
+    Ok(())
+}
+
+/// List a post's revisions, newest first. Old slugs find the post as they do
+/// for `get_post_by_slug`.
+pub fn list_revisions(conn: &Connection, slug: &str) -> Result<Vec<RevisionSummary>> {
+    let mut stmt = conn.prepare(
+        "SELECT r.revision, r.title, r.created_at
+         FROM post_revisions r JOIN posts p ON p.id = r.post_id
+         WHERE p.id = (SELECT id FROM posts WHERE slug = ?1
+                       UNION ALL
+                       SELECT post_id FROM slug_aliases WHERE slug = ?1
+                       LIMIT 1)
+           AND p.deleted_at IS NULL
+         ORDER BY r.revision DESC",
+    )?;
+
+    let mut rows = stmt.query([slug])?;
+    let mut revisions = Vec::new();
 
     while let Some(row) = rows.next()? {
-        let tags_str: String = row.get(5)?;
-        let tags: Vec<String> = serde_json::from_str(&tags_str).unwrap_or_default();
-        let created_str: String = row.get(3)?;
-        let published_str: Option<String> = row.get(4)?;
+        let created_str: String = row.get(2)?;
 
-        posts.push(PostSummary {
-            id: row.get(0)?,
-            slug: row.get(1)?,
-            title: row.get(2)?,
+        revisions.push(RevisionSummary {
+            revision: row.get(0)?,
+            title: row.get(1)?,
             created_at: DateTime::parse_from_rfc3339(&created_str)
                 .unwrap()
                 .with_timezone(&Utc),
-            published_at: published_str.map(|s| {
-                DateTime::parse_from_rfc3339(&s)
-                    .unwrap()
-                    .with_timezone(&Utc)
-            }),
-            tags,
         });
     }
 
-    Ok(posts)
+    Ok(revisions)
 }
 
-pub fn list_posts_full(conn: &Connection, limit: Option<usize>) -> Result<Vec<Post>> {
-    let sql = "SELECT id, slug, title, content, repo, created_at, published_at, tags, commit_range
-               FROM posts WHERE published_at IS NOT NULL ORDER BY published_at DESC";
+/// Fetch one revision of a post. `None` for the revision number means the latest.
+pub fn get_revision(conn: &Connection, slug: &str, revision: Option<i64>) -> Result<Option<Revision>> {
+    let mut stmt = conn.prepare(
+        "SELECT r.revision, r.title, r.content, r.tags, r.created_at
+         FROM post_revisions r JOIN posts p ON p.id = r.post_id
+         WHERE p.id = (SELECT id FROM posts WHERE slug = ?1
+                       UNION ALL
+                       SELECT post_id FROM slug_aliases WHERE slug = ?1
+                       LIMIT 1)
+           AND p.deleted_at IS NULL AND (?2 IS NULL OR r.revision = ?2)
+         ORDER BY r.revision DESC
+         LIMIT 1",
+    )?;
+
+    let mut rows = stmt.query((slug, revision))?;
+
+    if let Some(row) = rows.next()? {
+        let tags_str: String = row.get(3)?;
+        let created_str: String = row.get(4)?;
+
+        Ok(Some(Revision {
+            revision: row.get(0)?,
+            title: row.get(1)?,
+            content: row.get(2)?,
+            tags: serde_json::from_str(&tags_str).unwrap_or_default(),
+            created_at: DateTime::parse_from_rfc3339(&created_str)
+                .unwrap()
+                .with_timezone(&Utc),
+        }))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Put an old revision's text back. This goes through update_post, so the
+/// restore is itself recorded as a new revision rather than rewriting history.
+pub fn restore_revision(conn: &Connection, slug: &str, revision: i64) -> Result<bool> {
+    let old = match get_revision(conn, slug, Some(revision))? {
+        Some(r) => r,
+        None => return Ok(false),
+    };
+
+    let update = UpdatePost {
+        title: Some(old.title),
+        content: Some(old.content),
+        tags: Some(old.tags),
+        ..Default::default()
+    };
+
+    update_post(conn, slug, &update)
+}
+
+/// Line-level diff of the content between two revisions
+pub fn diff_revisions(from: &Revision, to: &Revision) -> RevisionDiff {
+    use similar::{ChangeTag, TextDiff};
+
+    let lines = TextDiff::from_lines(&from.content, &to.content)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            op: match change.tag() {
+                ChangeTag::Equal => "equal",
+                ChangeTag::Insert => "insert",
+                ChangeTag::Delete => "delete",
+            },
+            text: change.value().trim_end_matches('\n').to_string(),
+        })
+        .collect();
+
+    RevisionDiff {
+        from: RevisionSummary {
+            revision: from.revision,
+            title: from.title.clone(),
+            created_at: from.created_at,
+        },
+        to: RevisionSummary {
+            revision: to.revision,
+            title: to.title.clone(),
+            created_at: to.created_at,
+        },
+        tags_added: to.tags.iter().filter(|t| !from.tags.contains(t)).cloned().collect(),
+        tags_removed: from.tags.iter().filter(|t| !to.tags.contains(t)).cloned().collect(),
+        lines,
+    }
+}
+
+/// Finish publishing any post whose time has come: sync its project from the
+/// post, once. There's no scheduler, so open_db calls this on every request and
+/// a scheduled post's project appears with the first visitor after it goes live.
+pub fn publish_due_posts(conn: &Connection) -> Result<usize> {
+    let now = Utc::now().to_rfc3339();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM posts
+         WHERE status = 'published' AND published_at <= ?1
+           AND project_synced_at IS NULL AND deleted_at IS NULL",
+        POST_COLUMNS
+    ))?;
+
+    let mut due = Vec::new();
+    let mut rows = stmt.query([&now])?;
+    while let Some(row) = rows.next()? {
+        due.push(post_from_row(row)?);
+    }
+
+    for post in &due {
+        let new_post = NewPost {
+            title: post.title.clone(),
+            content: post.content.clone(),
+            repo: post.repo.clone(),
+            tags: Some(post.tags.clone()),
+            commit_range: post.commit_range.clone(),
+            publish: Some(true),
+            publish_at: None,
+            slug: None,
+            status: None,
+        };
+        let _ = sync_project_from_post(conn, &new_post);
+
+        conn.execute(
+            "UPDATE posts SET project_synced_at = ?1 WHERE id = ?2",
+            (&now, post.id),
+        )?;
+    }
+
+    Ok(due.len())
+}
+
+/// Move a post to the trash. It disappears everywhere but keeps its slug, so
+/// restore_post can bring it back exactly as it was.
+pub fn delete_post(conn: &Connection, slug: &str) -> Result<bool> {
+    let slug = match current_slug(conn, slug)? {
+        Some(s) => s,
+        None => return Ok(false),
+    };
+    let rows = conn.execute(
+        "UPDATE posts SET deleted_at = ?1 WHERE slug = ?2 AND deleted_at IS NULL",
+        (Utc::now().to_rfc3339(), slug),
+    )?;
+    Ok(rows > 0)
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrashedPost {
+    #[serde(flatten)]
+    pub post: PostSummary,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// List trashed posts, most recently deleted first
+pub fn list_trash(conn: &Connection) -> Result<Vec<TrashedPost>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}, posts.deleted_at FROM posts
+         WHERE deleted_at IS NOT NULL
+         ORDER BY deleted_at DESC",
+        SUMMARY_COLUMNS
+    ))?;
 
-    let mut stmt = conn.prepare(sql)?;
     let mut rows = stmt.query([])?;
     let mut posts = Vec::new();
 
     while let Some(row) = rows.next()? {
-        if let Some(max) = limit {
-            if posts.len() >= max {
-                break;
-            }
-        }
+        let deleted_str: String = row.get(7)?;
 
-        let tags_str: String = row.get(7)?;
-        let tags: Vec<String> = serde_json::from_str(&tags_str).unwrap_or_default();
-        let created_str: String = row.get(5)?;
-        let published_str: Option<String> = row.get(6)?;
-
-        posts.push(Post {
-            id: Some(row.get(0)?),
-            slug: row.get(1)?,
-            title: row.get(2)?,
-            content: row.get(3)?,
-            repo: row.get(4)?,
-            created_at: DateTime::parse_from_rfc3339(&created_str)
-                .unwrap()
-                .with_timezone(&Utc),
-            published_at: published_str.map(|s| {
-                DateTime::parse_from_rfc3339(&s)
-                    .unwrap()
-                    .with_timezone(&Utc)
-            }),
-            tags,
-            commit_range: row.get(8)?,
+        posts.push(TrashedPost {
+            post: summary_from_row(row)?,
+            deleted_at: parse_time(&deleted_str),
         });
     }
 
     Ok(posts)
 }
 
+pub fn restore_post(conn: &Connection, slug: &str) -> Result<bool> {
+    let rows = conn.execute(
+        "UPDATE posts SET deleted_at = NULL WHERE slug = ?1 AND deleted_at IS NOT NULL",
+        [slug],
+    )?;
+    Ok(rows > 0)
+}
+
+/// Permanently delete posts that have been in the trash for more than
+/// `older_than_days` days, along with their revisions, old slugs and tags.
+/// Returns how many posts went.
+pub fn purge_trash(conn: &Connection, older_than_days: i64) -> Result<usize> {
+    let cutoff = (Utc::now() - chrono::Duration::days(older_than_days)).to_rfc3339();
+    let doomed = "SELECT id FROM posts WHERE deleted_at IS NOT NULL AND deleted_at <= ?1";
+
+    for table in ["post_revisions", "slug_aliases", "post_tags", "preview_tokens"] {
+        conn.execute(
+            &format!("DELETE FROM {} WHERE post_id IN ({})", table, doomed),
+            [&cutoff],
+        )?;
+    }
+
+    conn.execute(&format!("DELETE FROM posts WHERE id IN ({})", doomed), [&cutoff])
+}
+
+/// Which posts a listing should return. The default is every published post.
+#[derive(Debug, Default)]
+pub struct PostFilter {
+    /// Include drafts, scheduled, unlisted and archived posts (admin views)
+    pub include_drafts: bool,
+    pub tag: Option<String>,
+    /// Project id, as derived from a "® Name" tag
+    pub project: Option<String>,
+    pub repo: Option<String>,
+    pub published_after: Option<DateTime<Utc>>,
+    pub published_before: Option<DateTime<Utc>>,
+}
+
+impl PostFilter {
+    /// SQL conditions on `posts` for this filter, joined with AND, plus their
+    /// parameters in order
+    fn where_clause(&self) -> (String, Vec<Box<dyn rusqlite::ToSql>>) {
+        let mut clauses = vec!["posts.deleted_at IS NULL"];
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+
+        if !self.include_drafts {
+            clauses.push("posts.status = 'published' AND posts.published_at <= ?");
+            params.push(Box::new(Utc::now().to_rfc3339()));
+        }
+        if let Some(tag) = &self.tag {
+            clauses.push(
+                "EXISTS (SELECT 1 FROM post_tags pt JOIN tags t ON t.id = pt.tag_id
+                         WHERE pt.post_id = posts.id AND t.name = ?)",
+            );
+            params.push(Box::new(tag.clone()));
+        }
+        if let Some(project) = &self.project {
+            clauses.push(
+                "EXISTS (SELECT 1 FROM post_tags pt JOIN tags t ON t.id = pt.tag_id
+                         WHERE pt.post_id = posts.id AND t.project_id = ?)",
+            );
+            params.push(Box::new(project.clone()));
+        }
+        if let Some(repo) = &self.repo {
+            clauses.push("posts.repo = ?");
+            params.push(Box::new(repo.clone()));
+        }
+        if let Some(after) = self.published_after {
+            clauses.push("posts.published_at >= ?");
+            params.push(Box::new(after.to_rfc3339()));
+        }
+        if let Some(before) = self.published_before {
+            clauses.push("posts.published_at < ?");
+            params.push(Box::new(before.to_rfc3339()));
+        }
+
+        (clauses.join(" AND "), params)
+    }
+}
+
+/// Position in a listing: the sort key and id of the last post already seen.
+/// Clients treat the encoded form as opaque.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    pub sort_key: String,
+    pub id: i64,
+}
+
+impl Cursor {
+    /// Hex, so it survives a query string without any escaping
+    pub fn encode(&self) -> String {
+        format!("{}|{}", self.sort_key, self.id)
+            .bytes()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    pub fn decode(s: &str) -> Option<Cursor> {
+        if !s.len().is_multiple_of(2) || !s.is_ascii() {
+            return None;
+        }
+        let bytes: Vec<u8> = (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
+            .collect::<std::result::Result<_, _>>()
+            .ok()?;
+        let text = String::from_utf8(bytes).ok()?;
+        let (sort_key, id) = text.rsplit_once('|')?;
+
+        Some(Cursor {
+            sort_key: sort_key.to_string(),
+            id: id.parse().ok()?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PostPage {
+    pub posts: Vec<PostSummary>,
+    /// Pass back as `cursor` for the next page; null on the last page
+    pub next_cursor: Option<String>,
+    /// Posts matching the filter across all pages
+    pub total: i64,
+}
+
+/// One page of posts, newest first, using keyset pagination on
+/// (published_at, id) - or (created_at, id) when drafts are included, since
+/// drafts have no published_at. With no limit the rest of the list is returned.
+pub fn list_posts(
+    conn: &Connection,
+    filter: &PostFilter,
+    limit: Option<usize>,
+    cursor: Option<&Cursor>,
+) -> Result<PostPage> {
+    let (conditions, mut params) = filter.where_clause();
+    let sort = if filter.include_drafts { "posts.created_at" } else { "posts.published_at" };
+
+    let total: i64 = {
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        conn.query_row(
+            &format!("SELECT COUNT(*) FROM posts WHERE {}", conditions),
+            param_refs.as_slice(),
+            |row| row.get(0),
+        )?
+    };
+
+    let mut sql = format!("SELECT {}, {} FROM posts WHERE {}", SUMMARY_COLUMNS, sort, conditions);
+    if let Some(c) = cursor {
+        sql.push_str(&format!(" AND ({0} < ? OR ({0} = ? AND posts.id < ?))", sort));
+        params.push(Box::new(c.sort_key.clone()));
+        params.push(Box::new(c.sort_key.clone()));
+        params.push(Box::new(c.id));
+    }
+    sql.push_str(&format!(" ORDER BY {} DESC, posts.id DESC", sort));
+    if let Some(n) = limit {
+        // One extra row tells us whether there's another page
+        sql.push_str(" LIMIT ?");
+        params.push(Box::new(n as i64 + 1));
+    }
+
+    let mut stmt = conn.prepare(&sql)?;
+    let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+    let mut rows = stmt.query(param_refs.as_slice())?;
+    let mut posts: Vec<PostSummary> = Vec::new();
+    let mut last_key = String::new();
+    let mut next_cursor = None;
+
+    while let Some(row) = rows.next()? {
+        if limit.is_some_and(|n| posts.len() == n) {
+            let id = posts.last().map_or(0, |p| p.id);
+            next_cursor = Some(Cursor { sort_key: last_key, id }.encode());
+            break;
+        }
+        posts.push(summary_from_row(row)?);
+        last_key = row.get(7)?;
+    }
+
+    Ok(PostPage { posts, next_cursor, total })
+}
+
+/// Full posts matching a filter, newest first, for feeds
+pub fn list_posts_full(conn: &Connection, filter: &PostFilter, limit: Option<usize>) -> Result<Vec<Post>> {
+    let (conditions, mut params) = filter.where_clause();
+    let sort = if filter.include_drafts { "posts.created_at" } else { "posts.published_at" };
+
+    let sql = format!(
+        "SELECT {} FROM posts WHERE {} ORDER BY {} DESC, posts.id DESC LIMIT ?",
+        POST_COLUMNS, conditions, sort
+    );
+
+    // LIMIT -1 is SQLite for no limit
+    params.push(Box::new(limit.map_or(-1, |n| n as i64)));
+
+    let mut stmt = conn.prepare(&sql)?;
+    let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+    let mut rows = stmt.query(param_refs.as_slice())?;
+    let mut posts = Vec::new();
+
+    while let Some(row) = rows.next()? {
+        posts.push(post_from_row(row)?);
+    }
+
+    Ok(posts)
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    pub post: PostSummary,
+    /// Matching excerpt of the markdown source, HTML-escaped, with hits
+    /// wrapped in <mark></mark>
+    pub snippet: String,
+}
+
+/// Turn free text from a search box into an FTS5 query. Every word is quoted so
+/// stray punctuation can't become query syntax, and the last word is a prefix
+/// match so results appear while someone is still typing.
+fn fts_query(input: &str) -> Option<String> {
+    let terms: Vec<String> = input
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+
+    if terms.is_empty() {
+        return None;
+    }
+
+    Some(format!("{}*", terms.join(" ")))
+}
+
+/// Escape a raw FTS5 snippet for HTML, then turn its \u{1}/\u{2} hit
+/// markers into <mark> tags
+fn mark_hits(snippet: &str) -> String {
+    crate::feeds::escape_xml(snippet)
+        .replace('\u{1}', "<mark>")
+        .replace('\u{2}', "</mark>")
+}
+
+pub fn search_posts(conn: &Connection, query: &str, include_drafts: bool) -> Result<Vec<SearchResult>> {
+    let fts = match fts_query(query) {
+        Some(q) => q,
+        None => return Ok(Vec::new()),
+    };
+
+    // bm25 weights follow the column order: title, content, tags. Hits are
+    // marked with control characters so the excerpt can be escaped before
+    // they become <mark> tags.
+    let sql = format!(
+        "SELECT {}, snippet(posts_fts, -1, char(1), char(2), '…', 24)
+         FROM posts_fts
+         JOIN posts ON posts.id = posts_fts.rowid
+         WHERE posts_fts MATCH ?1
+           AND posts.deleted_at IS NULL
+           AND (?2 OR (posts.status = 'published' AND posts.published_at <= ?3))
+         ORDER BY bm25(posts_fts, 10.0, 1.0, 5.0)
+         LIMIT 50",
+        SUMMARY_COLUMNS
+    );
+
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query((fts, include_drafts, Utc::now().to_rfc3339()))?;
+    let mut results = Vec::new();
+
+    while let Some(row) = rows.next()? {
+        results.push(SearchResult {
+            post: summary_from_row(row)?,
+            snippet: mark_hits(&row.get::<_, String>(7)?),
+        });
+    }
+
+    Ok(results)
+}
+
+// Tags
+
+#[derive(Debug, Serialize)]
+pub struct TagCount {
+    pub name: String,
+    /// Number of published posts carrying the tag
+    pub count: i64,
+    /// Set for project tags ("® Name")
+    pub project_id: Option<String>,
+}
+
+/// Replace a post's rows in post_tags, creating any tags not seen before
+fn set_post_tags(conn: &Connection, post_id: i64, tags: &[String]) -> Result<()> {
+    conn.execute("DELETE FROM post_tags WHERE post_id = ?1", [post_id])?;
+
+    for tag in tags {
+        let project_id = parse_project_tag(tag).map(|(id, _)| id);
+        conn.execute(
+            "INSERT OR IGNORE INTO tags (name, project_id) VALUES (?1, ?2)",
+            (tag, project_id),
+        )?;
+        conn.execute(
+            "INSERT OR IGNORE INTO post_tags (post_id, tag_id)
+             SELECT ?1, id FROM tags WHERE name = ?2",
+            (post_id, tag),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Every tag used by at least one published post, most used first
+pub fn list_tags(conn: &Connection) -> Result<Vec<TagCount>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.name, COUNT(*) AS n, t.project_id
+         FROM tags t
+         JOIN post_tags pt ON pt.tag_id = t.id
+         JOIN posts ON posts.id = pt.post_id
+         WHERE posts.status = 'published' AND posts.published_at <= ?1 AND posts.deleted_at IS NULL
+         GROUP BY t.id
+         ORDER BY n DESC, t.name",
+    )?;
+
+    let mut rows = stmt.query([Utc::now().to_rfc3339()])?;
+    let mut tags = Vec::new();
+
+    while let Some(row) = rows.next()? {
+        tags.push(TagCount {
+            name: row.get(0)?,
+            count: row.get(1)?,
+            project_id: row.get(2)?,
+        });
+    }
+
+    Ok(tags)
+}
+
 // Project functions
 
 /// Extract the first paragraph from markdown content (for project snippets)
@@ -362,7 +1161,7 @@ pub fn parse_project_tag(tag: &str) -> Option<(String, String)> {
 
 /// Sync project from post data - creates or updates project when post has project tag
 pub fn sync_project_from_post(conn: &Connection, post: &NewPost) -> Result<()> {
-    let tags = post.tags.as_ref().map(|t| t.as_slice()).unwrap_or(&[]);
+    let tags = post.tags.as_deref().unwrap_or(&[]);
 
     // Find first project tag
     let project_info = tags.iter()
@@ -415,18 +1214,26 @@ pub fn insert_project(conn: &Connection, project: &NewProject) -> Result<()> {
     Ok(())
 }
 
+/// Projects with at least one published post. A project whose posts have all
+/// been unpublished, archived or trashed is hidden, since its description came
+/// from one of them.
 pub fn list_projects(conn: &Connection) -> Result<Vec<Project>> {
     // Order projects by most recent post mention (published_at of posts with matching project tag)
     let sql = "SELECT DISTINCT p.id, p.name, p.repo, p.description, p.short_description, p.created_at,
                       COALESCE(MAX(posts.published_at), p.created_at) as last_mentioned
                FROM projects p
-               LEFT JOIN posts ON posts.published_at IS NOT NULL
-                   AND posts.tags LIKE '%® ' || p.name || '%'
+               LEFT JOIN tags t ON t.project_id = p.id
+               LEFT JOIN post_tags pt ON pt.tag_id = t.id
+               LEFT JOIN posts ON posts.id = pt.post_id
+                   AND posts.status = 'published'
+                   AND posts.published_at <= ?1
+                   AND posts.deleted_at IS NULL
                GROUP BY p.id, p.name, p.repo, p.description, p.short_description, p.created_at
+               HAVING MAX(posts.id) IS NOT NULL
                ORDER BY last_mentioned DESC";
 
     let mut stmt = conn.prepare(sql)?;
-    let mut rows = stmt.query([])?;
+    let mut rows = stmt.query([Utc::now().to_rfc3339()])?;
     let mut projects = Vec::new();
 
     while let Some(row) = rows.next()? {
@@ -447,13 +1254,22 @@ pub fn list_projects(conn: &Connection) -> Result<Vec<Project>> {
     Ok(projects)
 }
 
+/// A project, if list_projects would show it
 pub fn get_project_by_id(conn: &Connection, id: &str) -> Result<Option<Project>> {
     let mut stmt = conn.prepare(
         "SELECT id, name, repo, description, short_description, created_at
-         FROM projects WHERE id = ?1",
+         FROM projects
+         WHERE id = ?1
+           AND EXISTS (SELECT 1 FROM tags t
+                       JOIN post_tags pt ON pt.tag_id = t.id
+                       JOIN posts ON posts.id = pt.post_id
+                       WHERE t.project_id = projects.id
+                         AND posts.status = 'published'
+                         AND posts.published_at <= ?2
+                         AND posts.deleted_at IS NULL)",
     )?;
 
-    let mut rows = stmt.query([id])?;
+    let mut rows = stmt.query([id, &Utc::now().to_rfc3339()])?;
 
     if let Some(row) = rows.next()? {
         let created_str: String = row.get(5)?;
@@ -473,36 +1289,61 @@ pub fn get_project_by_id(conn: &Connection, id: &str) -> Result<Option<Project>>
     }
 }
 
-// Authentication
-pub fn api_key_path() -> String {
-    std::env::var("BLOG_API_KEY_PATH").unwrap_or_else(|_| "/etc/robocyril-api-key".to_string())
+// CGI helpers
+pub fn read_stdin() -> String {
+    use std::io::Read;
+    let mut buffer = String::new();
+    std::io::stdin().read_to_string(&mut buffer).unwrap_or_default();
+    buffer
 }
 
-pub fn check_auth() -> bool {
-    let expected = match std::fs::read_to_string(api_key_path()) {
-        Ok(key) => key.trim().to_string(),
-        Err(_) => return false,
-    };
+/// Decode a percent-encoded query string component ('+' is a space)
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = |b: u8| (b as char).to_digit(16);
+                match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                    (Some(hi), Some(lo)) => {
+                        out.push((hi * 16 + lo) as u8);
+                        i += 2;
+                    }
+                    _ => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
 
-    let provided = std::env::var("HTTP_X_CYRIL_KEY").unwrap_or_default();
-    !expected.is_empty() && provided == expected
+    String::from_utf8_lossy(&out).into_owned()
 }
 
-pub fn require_auth() -> bool {
-    if !check_auth() {
-        json_error(401, "Unauthorized");
-        false
-    } else {
-        true
-    }
+/// Percent-encode a query string component. Unreserved characters pass
+/// through; everything else, including every byte of non-ASCII text, is escaped.
+pub fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
 }
 
-// CGI helpers
-pub fn read_stdin() -> String {
-    use std::io::Read;
-    let mut buffer = String::new();
-    std::io::stdin().read_to_string(&mut buffer).unwrap_or_default();
-    buffer
+/// Look up a single decoded parameter from a query string
+pub fn query_param(query: &str, name: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let mut parts = pair.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(k), Some(v)) if percent_decode(k) == name => Some(percent_decode(v)),
+            _ => None,
+        }
+    })
 }
 
 pub fn cgi_response(status: u16, content_type: &str, body: &str) {
@@ -0,0 +1,448 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, Result};
+use serde::Serialize;
+
+/// A single schema change. Versions must be unique and strictly increasing,
+/// and a migration must never be edited once it has shipped - add a new one.
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: fn(&Connection) -> Result<()>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: String,
+    pub applied_at: Option<DateTime<Utc>>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create posts and projects",
+        up: m001_initial,
+    },
+    Migration {
+        version: 2,
+        name: "full-text search index on posts",
+        up: m002_posts_fts,
+    },
+    Migration {
+        version: 3,
+        name: "post revision history",
+        up: m003_post_revisions,
+    },
+    Migration {
+        version: 4,
+        name: "slug aliases for renamed posts",
+        up: m004_slug_aliases,
+    },
+    Migration {
+        version: 5,
+        name: "track project sync for scheduled posts",
+        up: m005_project_synced_at,
+    },
+    Migration {
+        version: 6,
+        name: "post status",
+        up: m006_post_status,
+    },
+    Migration {
+        version: 7,
+        name: "soft delete",
+        up: m007_soft_delete,
+    },
+    Migration {
+        version: 8,
+        name: "normalised tags",
+        up: m008_tags,
+    },
+    Migration {
+        version: 9,
+        name: "post modification time",
+        up: m009_updated_at,
+    },
+    Migration {
+        version: 10,
+        name: "scoped api keys",
+        up: m010_api_keys,
+    },
+    Migration {
+        version: 11,
+        name: "draft preview tokens",
+        up: m011_preview_tokens,
+    },
+    Migration {
+        version: 12,
+        name: "signed request nonces",
+        up: m012_request_nonces,
+    },
+    Migration {
+        version: 13,
+        name: "audit log",
+        up: m013_audit_log,
+    },
+    Migration {
+        version: 14,
+        name: "api key signing secrets",
+        up: m014_signing_secret,
+    },
+];
+
+// Uses IF NOT EXISTS so databases created before migrations existed are
+// adopted as version 1 without complaint.
+fn m001_initial(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS posts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            slug TEXT UNIQUE NOT NULL,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            repo TEXT,
+            created_at TEXT NOT NULL,
+            published_at TEXT,
+            tags TEXT NOT NULL DEFAULT '[]',
+            commit_range TEXT
+        );
+        CREATE INDEX IF NOT EXISTS idx_posts_published ON posts(published_at);
+        CREATE INDEX IF NOT EXISTS idx_posts_created ON posts(created_at);
+
+        CREATE TABLE IF NOT EXISTS projects (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            repo TEXT NOT NULL,
+            description TEXT NOT NULL,
+            short_description TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_projects_created ON projects(created_at);
+        ",
+    )
+}
+
+// External-content FTS5 table: the text lives in posts, the index is kept in
+// step by triggers. The delete/update triggers must hand FTS5 the *old* values.
+fn m002_posts_fts(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE VIRTUAL TABLE posts_fts USING fts5(
+            title,
+            content,
+            tags,
+            content = 'posts',
+            content_rowid = 'id',
+            tokenize = 'porter unicode61'
+        );
+
+        CREATE TRIGGER posts_fts_insert AFTER INSERT ON posts BEGIN
+            INSERT INTO posts_fts (rowid, title, content, tags)
+            VALUES (new.id, new.title, new.content, new.tags);
+        END;
+
+        CREATE TRIGGER posts_fts_delete AFTER DELETE ON posts BEGIN
+            INSERT INTO posts_fts (posts_fts, rowid, title, content, tags)
+            VALUES ('delete', old.id, old.title, old.content, old.tags);
+        END;
+
+        CREATE TRIGGER posts_fts_update AFTER UPDATE OF title, content, tags ON posts BEGIN
+            INSERT INTO posts_fts (posts_fts, rowid, title, content, tags)
+            VALUES ('delete', old.id, old.title, old.content, old.tags);
+            INSERT INTO posts_fts (rowid, title, content, tags)
+            VALUES (new.id, new.title, new.content, new.tags);
+        END;
+
+        INSERT INTO posts_fts (posts_fts) VALUES ('rebuild');
+        ",
+    )
+}
+
+// Every post starts with its current text as revision 1
+fn m003_post_revisions(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE post_revisions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            post_id INTEGER NOT NULL REFERENCES posts(id),
+            revision INTEGER NOT NULL,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            tags TEXT NOT NULL DEFAULT '[]',
+            created_at TEXT NOT NULL,
+            UNIQUE (post_id, revision)
+        );
+
+        INSERT INTO post_revisions (post_id, revision, title, content, tags, created_at)
+        SELECT id, 1, title, content, tags, created_at FROM posts;
+        ",
+    )
+}
+
+fn m004_slug_aliases(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE slug_aliases (
+            slug TEXT PRIMARY KEY,
+            post_id INTEGER NOT NULL REFERENCES posts(id),
+            created_at TEXT NOT NULL
+        );
+        CREATE INDEX idx_slug_aliases_post ON slug_aliases(post_id);
+        ",
+    )
+}
+
+// Posts already live have had their project synced by insert/update
+fn m005_project_synced_at(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        ALTER TABLE posts ADD COLUMN project_synced_at TEXT;
+        UPDATE posts SET project_synced_at = published_at WHERE published_at IS NOT NULL;
+        ",
+    )
+}
+
+// Stored status is draft, published, unlisted or archived. "Scheduled" is never
+// stored: it's a published post whose published_at hasn't arrived yet.
+fn m006_post_status(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        ALTER TABLE posts ADD COLUMN status TEXT NOT NULL DEFAULT 'draft';
+        UPDATE posts SET status = 'published' WHERE published_at IS NOT NULL;
+        CREATE INDEX idx_posts_status ON posts(status, published_at);
+        ",
+    )
+}
+
+fn m007_soft_delete(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        ALTER TABLE posts ADD COLUMN deleted_at TEXT;
+        CREATE INDEX idx_posts_deleted ON posts(deleted_at);
+        ",
+    )
+}
+
+// posts.tags stays as the JSON source of truth for reading a post back; these
+// tables exist so tags can be queried and counted. project_id caches
+// parse_project_tag so project pages can join on it instead of LIKE-matching.
+fn m008_tags(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE tags (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT UNIQUE NOT NULL,
+            project_id TEXT
+        );
+        CREATE INDEX idx_tags_project ON tags(project_id);
+
+        CREATE TABLE post_tags (
+            post_id INTEGER NOT NULL REFERENCES posts(id),
+            tag_id INTEGER NOT NULL REFERENCES tags(id),
+            PRIMARY KEY (post_id, tag_id)
+        );
+        CREATE INDEX idx_post_tags_tag ON post_tags(tag_id);
+
+        INSERT OR IGNORE INTO tags (name)
+        SELECT DISTINCT json_each.value FROM posts, json_each(posts.tags);
+
+        INSERT OR IGNORE INTO post_tags (post_id, tag_id)
+        SELECT posts.id, tags.id
+        FROM posts, json_each(posts.tags)
+        JOIN tags ON tags.name = json_each.value;
+        ",
+    )?;
+
+    let mut stmt = conn.prepare("SELECT id, name FROM tags")?;
+    let tags: Vec<(i64, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_>>()?;
+
+    for (id, name) in tags {
+        if let Some((project_id, _)) = crate::parse_project_tag(&name) {
+            conn.execute("UPDATE tags SET project_id = ?1 WHERE id = ?2", (project_id, id))?;
+        }
+    }
+
+    Ok(())
+}
+
+// The newest revision is the best record we have of when a post last changed
+fn m009_updated_at(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        ALTER TABLE posts ADD COLUMN updated_at TEXT;
+        UPDATE posts SET updated_at = COALESCE(
+            (SELECT MAX(created_at) FROM post_revisions WHERE post_id = posts.id),
+            created_at
+        );
+        ",
+    )
+}
+
+// Keys are stored hashed; scopes are a JSON array as posts.tags is
+fn m010_api_keys(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE api_keys (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            label TEXT NOT NULL,
+            key_hash TEXT UNIQUE NOT NULL,
+            scopes TEXT NOT NULL DEFAULT '[]',
+            created_at TEXT NOT NULL,
+            expires_at TEXT,
+            last_used_at TEXT,
+            revoked_at TEXT
+        );
+        ",
+    )
+}
+
+// Hashed like api keys; the token itself only ever appears in the link
+fn m011_preview_tokens(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE preview_tokens (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            post_id INTEGER NOT NULL REFERENCES posts(id),
+            token_hash TEXT UNIQUE NOT NULL,
+            created_at TEXT NOT NULL,
+            expires_at TEXT NOT NULL,
+            revoked_at TEXT
+        );
+
+        CREATE INDEX idx_preview_tokens_post ON preview_tokens(post_id);
+        ",
+    )
+}
+
+// seen_at is unix seconds, so pruning old nonces is a plain comparison
+fn m012_request_nonces(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE request_nonces (
+            key_id INTEGER NOT NULL REFERENCES api_keys(id),
+            nonce TEXT NOT NULL,
+            seen_at INTEGER NOT NULL,
+            PRIMARY KEY (key_id, nonce)
+        );
+        ",
+    )
+}
+
+// No foreign key to posts: entries outlive the posts they describe
+fn m013_audit_log(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE audit_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            created_at TEXT NOT NULL,
+            key_label TEXT NOT NULL,
+            remote_addr TEXT NOT NULL,
+            action TEXT NOT NULL,
+            slug TEXT NOT NULL,
+            changes TEXT NOT NULL DEFAULT '{}'
+        );
+
+        CREATE INDEX idx_audit_log_slug ON audit_log(slug);
+        CREATE INDEX idx_audit_log_created ON audit_log(created_at);
+        ",
+    )
+}
+
+// Signed requests are keyed by this rather than anything derived from the key.
+// Keys issued before it existed have none and can't sign until reissued.
+fn m014_signing_secret(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        ALTER TABLE api_keys ADD COLUMN signing_secret TEXT;
+        ",
+    )
+}
+
+fn ensure_migrations_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at TEXT NOT NULL
+        );
+        ",
+    )
+}
+
+fn applied_at(conn: &Connection, version: i64) -> Result<Option<DateTime<Utc>>> {
+    let applied: Option<String> = conn
+        .query_row(
+            "SELECT applied_at FROM schema_migrations WHERE version = ?1",
+            [version],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(applied.map(|s| {
+        DateTime::parse_from_rfc3339(&s)
+            .unwrap()
+            .with_timezone(&Utc)
+    }))
+}
+
+/// Apply every pending migration in version order. Each step runs in its own
+/// transaction together with its schema_migrations row, so a failure leaves
+/// the database at the last good version. Returns the versions applied.
+pub fn migrate(conn: &Connection) -> Result<Vec<i64>> {
+    ensure_migrations_table(conn)?;
+
+    let mut applied = Vec::new();
+
+    for migration in MIGRATIONS {
+        if applied_at(conn, migration.version)?.is_some() {
+            continue;
+        }
+
+        conn.execute_batch("BEGIN IMMEDIATE")?;
+
+        // Another process may have got there while we waited for the lock
+        if applied_at(conn, migration.version)?.is_some() {
+            conn.execute_batch("COMMIT")?;
+            continue;
+        }
+
+        let result = (migration.up)(conn).and_then(|_| {
+            conn.execute(
+                "INSERT INTO schema_migrations (version, name, applied_at) VALUES (?1, ?2, ?3)",
+                (migration.version, migration.name, Utc::now().to_rfc3339()),
+            )
+        });
+
+        match result {
+            Ok(_) => conn.execute_batch("COMMIT")?,
+            Err(e) => {
+                let _ = conn.execute_batch("ROLLBACK");
+                return Err(e);
+            }
+        }
+
+        conn.pragma_update(None, "user_version", migration.version)?;
+        applied.push(migration.version);
+    }
+
+    Ok(applied)
+}
+
+/// Report every known migration and when (if ever) it was applied
+pub fn migration_status(conn: &Connection) -> Result<Vec<MigrationStatus>> {
+    ensure_migrations_table(conn)?;
+
+    let mut status = Vec::new();
+    for migration in MIGRATIONS {
+        status.push(MigrationStatus {
+            version: migration.version,
+            name: migration.name.to_string(),
+            applied_at: applied_at(conn, migration.version)?,
+        });
+    }
+
+    Ok(status)
+}
@@ -0,0 +1,14 @@
+use robocyril_api::handlers::{page, Request, Response};
+use robocyril_api::open_db;
+
+fn main() {
+    let conn = match open_db() {
+        Ok(c) => c,
+        Err(e) => {
+            Response::html_error(500, &format!("Database error: {}", e)).send_cgi();
+            return;
+        }
+    };
+
+    page(&conn, &Request::from_cgi()).send_cgi();
+}
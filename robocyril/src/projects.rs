@@ -1,4 +1,5 @@
-use robocyril_api::{open_db, list_projects, json_ok, json_error};
+use robocyril_api::handlers::{projects, Request};
+use robocyril_api::{json_error, open_db};
 
 fn main() {
     let conn = match open_db() {
@@ -9,8 +10,5 @@ fn main() {
         }
     };
 
-    match list_projects(&conn) {
-        Ok(projects) => json_ok(&projects),
-        Err(e) => json_error(500, &format!("Query failed: {}", e)),
-    }
+    projects(&conn, &Request::from_cgi()).send_cgi();
 }
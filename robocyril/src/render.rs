@@ -0,0 +1,155 @@
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
+use std::collections::HashMap;
+
+fn options() -> Options {
+    Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES
+}
+
+/// Render post markdown to sanitised HTML: headings get slugged `id`s for
+/// linking, fenced code keeps its `language-xxx` class, and footnotes and
+/// tables are supported. Raw HTML in the markdown goes through the sanitiser
+/// like everything else, so the result is safe to drop into a page.
+pub fn markdown_to_html(content: &str) -> String {
+    let events = with_heading_anchors(Parser::new_ext(content, options()).collect());
+    let mut out = String::with_capacity(content.len() * 3 / 2);
+    html::push_html(&mut out, events.into_iter());
+    sanitise(&out)
+}
+
+/// Give every heading an id derived from its text. Repeats get -2, -3... as
+/// slugs do, so the same heading twice still links to two places.
+fn with_heading_anchors(mut events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+
+    for i in 0..events.len() {
+        if !matches!(events[i], Event::Start(Tag::Heading { id: None, .. })) {
+            continue;
+        }
+
+        let text: String = events[i + 1..]
+            .iter()
+            .take_while(|e| !matches!(e, Event::End(TagEnd::Heading(_))))
+            .filter_map(|e| match e {
+                Event::Text(t) | Event::Code(t) => Some(t.as_ref()),
+                _ => None,
+            })
+            .collect();
+
+        let base = match slug::slugify(&text) {
+            s if s.is_empty() => "section".to_string(),
+            s => s,
+        };
+        let count = seen.entry(base.clone()).or_insert(0);
+        *count += 1;
+        let anchor = if *count == 1 { base } else { format!("{}-{}", base, count) };
+
+        if let Event::Start(Tag::Heading { id, .. }) = &mut events[i] {
+            *id = Some(CowStr::from(anchor));
+        }
+    }
+
+    events
+}
+
+// Classes pulldown-cmark emits that we want to keep; anything else is dropped
+const FOOTNOTE_CLASSES: &[&str] = &["footnote-reference", "footnote-definition", "footnote-definition-label"];
+
+fn sanitise(html: &str) -> String {
+    let headings = ["h1", "h2", "h3", "h4", "h5", "h6"];
+
+    let mut builder = ammonia::Builder::default();
+    for tag in headings {
+        builder.add_tag_attributes(tag, &["id"]);
+    }
+    builder
+        .add_tag_attributes("div", &["id", "class"])
+        .add_tag_attributes("sup", &["class"])
+        .add_tag_attributes("code", &["class"])
+        .add_tag_attributes("th", &["style"])
+        .add_tag_attributes("td", &["style"])
+        .attribute_filter(|element, attribute, value| match (element, attribute) {
+            ("code", "class") => value
+                .split_whitespace()
+                .all(|c| c.starts_with("language-"))
+                .then(|| value.into()),
+            (_, "class") => value
+                .split_whitespace()
+                .all(|c| FOOTNOTE_CLASSES.contains(&c))
+                .then(|| value.into()),
+            // Table column alignment is the only inline style we emit
+            (_, "style") => value
+                .strip_prefix("text-align: ")
+                .filter(|align| ["left", "center", "right"].contains(align))
+                .map(|_| value.into()),
+            _ => Some(value.into()),
+        });
+
+    builder.clean(html).to_string()
+}
+
+/// Reduce post markdown to plain text: markup dropped, one blank line
+/// between blocks, code blocks kept verbatim.
+pub fn markdown_to_text(content: &str) -> String {
+    let mut out = String::with_capacity(content.len());
+
+    for event in Parser::new_ext(content, options()) {
+        match event {
+            Event::Text(text) | Event::Code(text) => out.push_str(&text),
+            Event::SoftBreak => out.push(' '),
+            Event::HardBreak => out.push('\n'),
+            Event::End(TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::CodeBlock) => {
+                out.truncate(out.trim_end().len());
+                out.push_str("\n\n");
+            }
+            Event::End(TagEnd::Item) => {
+                out.truncate(out.trim_end().len());
+                out.push('\n');
+            }
+            Event::End(TagEnd::List(_) | TagEnd::Table) => out.push('\n'),
+            Event::End(TagEnd::TableCell) => out.push('\t'),
+            Event::End(TagEnd::TableHead | TagEnd::TableRow) => {
+                out.truncate(out.trim_end().len());
+                out.push('\n');
+            }
+            _ => {}
+        }
+    }
+
+    out.trim().to_string()
+}
+
+/// Plain-text summary of a post's prose for feed descriptions. Headings, code
+/// blocks, tables and footnotes are skipped, and the text is cut at a word
+/// boundary.
+pub fn markdown_summary(content: &str, max_chars: usize) -> String {
+    let mut words = String::new();
+    let mut skipping = 0;
+
+    for event in Parser::new_ext(content, options()) {
+        match event {
+            Event::Start(
+                Tag::Heading { .. } | Tag::CodeBlock(_) | Tag::Table(_) | Tag::FootnoteDefinition(_),
+            ) => skipping += 1,
+            Event::End(TagEnd::Heading(_) | TagEnd::CodeBlock | TagEnd::Table | TagEnd::FootnoteDefinition) => {
+                skipping -= 1
+            }
+            Event::Text(text) | Event::Code(text) if skipping == 0 => words.push_str(&text),
+            Event::SoftBreak | Event::HardBreak if skipping == 0 => words.push(' '),
+            Event::End(TagEnd::Paragraph | TagEnd::Item) => words.push(' '),
+            _ => {}
+        }
+    }
+
+    let plain = words.split_whitespace().collect::<Vec<_>>().join(" ");
+    if plain.chars().count() <= max_chars {
+        return plain;
+    }
+
+    // Count in chars, not bytes, so multi-byte text isn't cut mid-character
+    let cut: String = plain.chars().take(max_chars).collect();
+    let cut = match cut.rfind(' ') {
+        Some(space) => &cut[..space],
+        None => &cut,
+    };
+    format!("{}...", cut.trim_end())
+}
@@ -0,0 +1,166 @@
+use robocyril_api::handlers::{route, spawn_workers, Request, Response};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+const USAGE: &str = "Usage: blog-serve [address]
+
+Serves every blog endpoint over HTTP from one process (default 127.0.0.1:8080).
+Paths are as in the nginx config - /api/posts, /feed.xml and so on - optionally
+under the path of BLOG_BASE_URL, so /cyril/api/posts works too. Put it behind a
+reverse proxy for TLS; it speaks plain HTTP/1.1 and closes every connection.
+From loopback peers, X-Real-IP or X-Forwarded-For gives the client address.";
+
+const WORKERS: usize = 4;
+const MAX_BODY: usize = 1024 * 1024;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let addr = match args.as_slice() {
+        [] => "127.0.0.1:8080".to_string(),
+        [addr] if !addr.starts_with('-') => addr.clone(),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let listener = match TcpListener::bind(&addr) {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("Failed to listen on {}: {}", addr, e);
+            std::process::exit(1);
+        }
+    };
+
+    let sender = match spawn_workers(WORKERS, serve_connection) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Failed to open database: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("Listening on http://{}/", addr);
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(s) => {
+                let _ = sender.send(s);
+            }
+            Err(e) => eprintln!("Accept failed: {}", e),
+        }
+    }
+}
+
+fn serve_connection(conn: &rusqlite::Connection, mut stream: TcpStream) {
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(10)));
+
+    let response = match read_request(&stream) {
+        Ok((path, mut request)) => {
+            request.remote_addr = match stream.peer_addr() {
+                Ok(peer) => client_addr(peer.ip(), &request),
+                Err(_) => String::new(),
+            };
+
+            let response = route(conn, &path, &request);
+            eprintln!("{} {} {}", request.method, path, response.status);
+            response
+        }
+        Err(response) => response,
+    };
+
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    );
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(response.body.as_bytes());
+}
+
+/// The address to record for a request. Behind a reverse proxy on the same
+/// machine every peer is loopback, so then the proxy's X-Real-IP, or the last
+/// X-Forwarded-For entry (the one the proxy added), names the client. Those
+/// headers are ignored from anyone else, who could set them to anything.
+fn client_addr(peer: IpAddr, request: &Request) -> String {
+    if peer.is_loopback() {
+        let forwarded = request
+            .header("x-real-ip")
+            .or_else(|| request.header("x-forwarded-for").and_then(|v| v.rsplit(',').next()))
+            .and_then(|addr| addr.trim().parse::<IpAddr>().ok());
+        if let Some(addr) = forwarded {
+            return addr.to_string();
+        }
+    }
+    peer.to_string()
+}
+
+/// Parse an HTTP/1.1 request into its path and a Request
+fn read_request(stream: &TcpStream) -> Result<(String, Request), Response> {
+    let bad_request = || Response::error(400, "Bad request");
+    let mut reader = BufReader::new(stream);
+
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|_| bad_request())?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(m), Some(t)) => (m.to_string(), t.to_string()),
+        _ => return Err(bad_request()),
+    };
+
+    let mut request = Request {
+        method,
+        ..Default::default()
+    };
+    loop {
+        line.clear();
+        reader.read_line(&mut line).map_err(|_| bad_request())?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            request
+                .headers
+                .insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let length: usize = match request.header("content-length") {
+        Some(n) => n.parse().map_err(|_| bad_request())?,
+        None => 0,
+    };
+    if length > MAX_BODY {
+        return Err(Response::error(413, "Request body too large"));
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(|_| bad_request())?;
+    request.body = String::from_utf8(body).map_err(|_| bad_request())?;
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    request.path = path.to_string();
+    request.query = query.to_string();
+
+    Ok((path.to_string(), request))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        301 => "Moved Permanently",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        500 => "Internal Server Error",
+        // The reason phrase is informational; clients go by the code
+        _ => "",
+    }
+}
@@ -0,0 +1,262 @@
+use crate::feeds::{escape_xml, FeedConfig};
+use crate::render::markdown_to_html;
+use crate::{extract_first_paragraph, list_posts_full, percent_encode, Post, PostFilter, PostStatus};
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, Result};
+
+// Server-rendered HTML for when the SPA isn't there to do it: the static
+// export, and pages for crawlers. Kept deliberately plain.
+
+const STYLE: &str = "
+body { max-width: 46rem; margin: 0 auto; padding: 1rem; font: 1rem/1.6 Inter, sans-serif; color: #222; }
+header, footer { font-family: 'IBM Plex Mono', monospace; }
+header nav a, footer a { margin-right: 1rem; }
+pre { overflow-x: auto; padding: 0.75rem; background: #f4f1ea; }
+code { font-family: 'IBM Plex Mono', monospace; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.25rem 0.5rem; }
+.meta, .tags { color: #666; font-size: 0.9rem; }
+.tags a { margin-right: 0.5rem; }
+.posts { list-style: none; padding: 0; }
+.posts li { margin-bottom: 1rem; }
+";
+
+/// Dates as readers see them, e.g. "18 October 2026"
+pub fn display_date(dt: DateTime<Utc>) -> String {
+    dt.format("%-d %B %Y").to_string()
+}
+
+/// Wrap a page body in the shared shell. `root` is the relative path back to
+/// the site root from this page ("" or "../"), so an export works wherever it's
+/// served from; `head` is extra markup for `<head>`. Outside a static export
+/// the header links back into the SPA instead.
+pub fn layout(config: &FeedConfig, root: &str, title: &str, head: &str, body: &str) -> String {
+    let (home, nav) = if config.static_site {
+        (
+            format!("{}index.html", root),
+            format!(
+                "<a href=\"{0}index.html\">Posts</a><a href=\"{0}tags/index.html\">Tags</a><a href=\"{0}projects/index.html\">Projects</a><a href=\"{0}feed.xml\">Feed</a>",
+                root
+            ),
+        )
+    } else {
+        let base = escape_xml(&config.base_url);
+        (
+            format!("{}/", base),
+            format!("<a href=\"{0}/\">Posts</a><a href=\"{0}/feed.xml\">Feed</a>", base),
+        )
+    };
+
+    format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+<meta name="viewport" content="width=device-width, initial-scale=1.0">
+<title>{title}</title>
+<link rel="alternate" type="application/rss+xml" title="{site}" href="{feed}">
+{head}<style>{style}</style>
+</head>
+<body>
+<header>
+<h1><a href="{home}">{site}</a></h1>
+<nav>{nav}</nav>
+</header>
+<main>
+{body}
+</main>
+<footer><p>{description}</p></footer>
+</body>
+</html>
+"#,
+        title = escape_xml(title),
+        site = escape_xml(&config.title),
+        feed = escape_xml(&config.feed_url(None)),
+        description = escape_xml(&config.description),
+        head = head,
+        style = STYLE,
+        home = home,
+        nav = nav,
+        body = body,
+    )
+}
+
+/// One post in full. `tag_href` gives the link for a tag, if it has a page.
+pub fn post_article(post: &Post, tag_href: &dyn Fn(&str) -> Option<String>) -> String {
+    let date = post
+        .published_at
+        .map(|dt| format!("<time datetime=\"{}\">{}</time>", dt.to_rfc3339(), display_date(dt)))
+        .unwrap_or_default();
+
+    format!(
+        "<article>\n<h1>{}</h1>\n<p class=\"meta\">{}</p>\n{}\n{}</article>\n",
+        escape_xml(&post.title),
+        date,
+        tag_list(&post.tags, tag_href),
+        markdown_to_html(&post.content)
+    )
+}
+
+/// Newest-first list of posts linking to their pages
+pub fn post_list(
+    posts: &[&Post],
+    post_href: &dyn Fn(&Post) -> String,
+    tag_href: &dyn Fn(&str) -> Option<String>,
+) -> String {
+    let mut items = String::new();
+
+    for post in posts {
+        let date = post.published_at.map(display_date).unwrap_or_default();
+        items.push_str(&format!(
+            "<li><a href=\"{}\">{}</a> <span class=\"meta\">{}</span>\n{}</li>\n",
+            escape_xml(&post_href(post)),
+            escape_xml(&post.title),
+            date,
+            tag_list(&post.tags, tag_href)
+        ));
+    }
+
+    format!("<ul class=\"posts\">\n{}</ul>\n", items)
+}
+
+fn tag_list(tags: &[String], tag_href: &dyn Fn(&str) -> Option<String>) -> String {
+    if tags.is_empty() {
+        return String::new();
+    }
+
+    let links: Vec<String> = tags
+        .iter()
+        .map(|tag| match tag_href(tag) {
+            Some(href) => format!("<a href=\"{}\">{}</a>", escape_xml(&href), escape_xml(tag)),
+            None => format!("<span>{}</span>", escape_xml(tag)),
+        })
+        .collect();
+
+    format!("<p class=\"tags\">{}</p>", links.join(""))
+}
+
+/// A single post as a real HTML document, for link previews: chat apps and
+/// social sites read the OpenGraph and Twitter card tags without running any
+/// JavaScript. Browsers are sent on to the SPA's route for the post; the
+/// canonical URL and og:url name this page, which crawlers can fetch.
+pub fn post_page(config: &FeedConfig, post: &Post) -> String {
+    let url = config.page_url(post);
+    let description = extract_first_paragraph(&post.content);
+
+    let mut meta = vec![
+        ("og:type", "article".to_string()),
+        ("og:title", post.title.clone()),
+        ("og:description", description.clone()),
+        ("og:url", url.clone()),
+        ("og:site_name", config.title.clone()),
+    ];
+    if let Some(published) = post.published_at {
+        meta.push(("article:published_time", published.to_rfc3339()));
+    }
+    meta.push(("article:modified_time", post.last_modified().to_rfc3339()));
+    meta.push(("article:author", config.author.clone()));
+    for tag in &post.tags {
+        meta.push(("article:tag", tag.clone()));
+    }
+
+    let mut head = format!(
+        "<meta name=\"description\" content=\"{}\">\n<link rel=\"canonical\" href=\"{}\">\n",
+        escape_xml(&description),
+        escape_xml(&url)
+    );
+    for (property, content) in &meta {
+        head.push_str(&format!(
+            "<meta property=\"{}\" content=\"{}\">\n",
+            property,
+            escape_xml(content)
+        ));
+    }
+    head.push_str(&format!(
+        "<meta name=\"twitter:card\" content=\"summary\">\n<meta name=\"twitter:title\" content=\"{}\">\n<meta name=\"twitter:description\" content=\"{}\">\n",
+        escape_xml(&post.title),
+        escape_xml(&description)
+    ));
+    if post.status == PostStatus::Unlisted {
+        head.push_str("<meta name=\"robots\" content=\"noindex\">\n");
+    }
+
+    // serde_json gives a safe JS string literal; escaping "<" as well keeps
+    // "</script>" in a slug from closing the tag early
+    let target = serde_json::to_string(&config.post_url(post)).unwrap().replace('<', "\\u003c");
+    let body = format!(
+        "{}<script>window.location.replace({});</script>\n",
+        post_article(post, &|tag| Some(format!("{}/#/?tag={}", config.base_url, percent_encode(tag)))),
+        target
+    );
+
+    let title = format!("{} - {}", post.title, config.title);
+    layout(config, "", &title, &head, &body)
+}
+
+pub struct SitemapUrl {
+    pub loc: String,
+    pub lastmod: Option<DateTime<Utc>>,
+}
+
+/// sitemaps.org XML for the given absolute URLs
+pub fn sitemap(urls: &[SitemapUrl]) -> String {
+    let mut entries = String::new();
+
+    for url in urls {
+        let lastmod = url
+            .lastmod
+            .map(|dt| format!("\n    <lastmod>{}</lastmod>", dt.format("%Y-%m-%dT%H:%M:%SZ")))
+            .unwrap_or_default();
+        entries.push_str(&format!(
+            "  <url>\n    <loc>{}</loc>{}\n  </url>\n",
+            escape_xml(&url.loc),
+            lastmod
+        ));
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+{}</urlset>
+"#,
+        entries
+    )
+}
+
+/// Sitemap of the live site: the home page and every published post's
+/// blog-page address. Drafts, scheduled, unlisted and archived posts are left
+/// out, as they are from the listing. Project pages only exist as SPA routes,
+/// which crawlers see as the home page, so they aren't listed; the static
+/// export's sitemap has real ones.
+pub fn site_sitemap(conn: &Connection, config: &FeedConfig) -> Result<String> {
+    let posts = list_posts_full(conn, &PostFilter::default(), None)?;
+    let mut urls = vec![SitemapUrl {
+        loc: format!("{}/", config.base_url),
+        lastmod: posts.iter().map(Post::last_modified).max(),
+    }];
+
+    for post in &posts {
+        urls.push(SitemapUrl { loc: config.page_url(post), lastmod: Some(post.last_modified()) });
+    }
+
+    Ok(sitemap(&urls))
+}
+
+/// robots.txt pointing crawlers at the sitemap and away from the API
+pub fn robots_txt(config: &FeedConfig) -> String {
+    format!(
+        "User-agent: *\nDisallow: {}/api/\n\nSitemap: {}/sitemap.xml\n",
+        mount_path(&config.base_url),
+        config.base_url
+    )
+}
+
+/// The path a base URL mounts the blog at: "/cyril" for https://host/cyril,
+/// "" when it's at the root
+pub fn mount_path(base_url: &str) -> &str {
+    base_url
+        .split_once("://")
+        .and_then(|(_, rest)| rest.find('/').map(|i| &rest[i..]))
+        .unwrap_or("")
+        .trim_end_matches('/')
+}
@@ -0,0 +1,20 @@
+use robocyril_api::handlers::{robots, sitemap, Request, Response};
+use robocyril_api::open_db;
+
+fn main() {
+    let conn = match open_db() {
+        Ok(c) => c,
+        Err(e) => {
+            Response::xml_error(500, &format!("Database error: {}", e)).send_cgi();
+            return;
+        }
+    };
+
+    // The same binary answers for /robots.txt
+    let script = std::env::var("SCRIPT_NAME").unwrap_or_default();
+    if script.ends_with("robots.txt") {
+        robots(&conn, &Request::from_cgi()).send_cgi();
+    } else {
+        sitemap(&conn, &Request::from_cgi()).send_cgi();
+    }
+}
@@ -0,0 +1,14 @@
+use robocyril_api::handlers::{trash, Request};
+use robocyril_api::{json_error, open_db};
+
+fn main() {
+    let conn = match open_db() {
+        Ok(c) => c,
+        Err(e) => {
+            json_error(500, &format!("Database error: {}", e));
+            return;
+        }
+    };
+
+    trash(&conn, &Request::from_cgi()).send_cgi();
+}